@@ -0,0 +1,464 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    hash::Hash,
+};
+
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{
+    network::Address,
+    primitives::{GUID, GUID_BYTES},
+    simulator::Time,
+};
+
+/// Compact binary encoding.
+///
+/// Integers are LEB128 varints, GUIDs their raw big-endian bytes, and
+/// collections are prefixed with their length. Enums start with a one byte
+/// tag.
+pub trait Encode {
+    fn encode(&self, out: &mut Vec<u8>);
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode(&mut out);
+        out
+    }
+}
+
+/// Inverse of [`Encode`].
+pub trait Decode: Sized {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError>;
+
+    /// Decodes a value taking up the whole of `bytes`.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut input = Decoder::new(bytes);
+        let value = Self::decode(&mut input)?;
+        input.finish()?;
+
+        Ok(value)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DecodeError {
+    /// The input ended in the middle of a value.
+    UnexpectedEnd,
+    /// An enum tag does not match any variant of `kind`.
+    TagInvalid { kind: &'static str, tag: u8 },
+    /// A varint does not fit the integer it is decoded into.
+    IntegerOverflow,
+    /// A value is out of the range of valid values of `kind`.
+    ValueInvalid { kind: &'static str },
+    /// Bytes were left after the value.
+    TrailingBytes { count: usize },
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "Unexpected end of input"),
+            DecodeError::TagInvalid { kind, tag } => write!(f, "Invalid {kind} tag {tag}"),
+            DecodeError::IntegerOverflow => write!(f, "Integer overflow"),
+            DecodeError::ValueInvalid { kind } => write!(f, "Invalid {kind}"),
+            DecodeError::TrailingBytes { count } => {
+                write!(f, "{count} unexpected bytes after the end of input")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Reads values off a byte slice.
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, DecodeError> {
+        let (byte, rest) = self.bytes.split_first().ok_or(DecodeError::UnexpectedEnd)?;
+        self.bytes = rest;
+        Ok(*byte)
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if len > self.bytes.len() {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(bytes)
+    }
+
+    pub fn read_varint(&mut self) -> Result<u128, DecodeError> {
+        let mut value = 0u128;
+
+        for shift in (0..u128::BITS).step_by(7) {
+            let byte = self.read_u8()?;
+            let bits = u128::from(byte & 0x7f);
+
+            if bits.checked_shl(shift).map(|v| v >> shift) != Some(bits) {
+                return Err(DecodeError::IntegerOverflow);
+            }
+            value |= bits << shift;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+
+        Err(DecodeError::IntegerOverflow)
+    }
+
+    /// Reads the length of a collection, which cannot be larger than the
+    /// remaining input since every element takes up at least one byte.
+    pub fn read_len(&mut self) -> Result<usize, DecodeError> {
+        let len = usize::decode(self)?;
+
+        if len > self.bytes.len() {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+
+        Ok(len)
+    }
+
+    /// Fails if any input is left.
+    pub fn finish(&self) -> Result<(), DecodeError> {
+        match self.bytes.len() {
+            0 => Ok(()),
+            count => Err(DecodeError::TrailingBytes { count }),
+        }
+    }
+}
+
+pub fn write_varint(out: &mut Vec<u8>, mut value: u128) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Encodes an enum tag.
+pub fn write_tag(out: &mut Vec<u8>, tag: u8) {
+    out.push(tag);
+}
+
+macro_rules! varint {
+    ($($int:ty),*) => {
+        $(
+            impl Encode for $int {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    write_varint(out, *self as u128);
+                }
+            }
+
+            impl Decode for $int {
+                fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+                    <$int>::try_from(input.read_varint()?).map_err(|_| DecodeError::IntegerOverflow)
+                }
+            }
+        )*
+    };
+}
+
+varint!(u16, u32, u64, u128, usize);
+
+impl Encode for u8 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+}
+
+impl Decode for u8 {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        input.read_u8()
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(u8::from(*self));
+    }
+}
+
+impl Decode for bool {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        match input.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(DecodeError::TagInvalid { kind: "bool", tag }),
+        }
+    }
+}
+
+impl Encode for f64 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+impl Decode for f64 {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        let bytes = input.read_bytes(8)?;
+        Ok(f64::from_le_bytes(bytes.try_into().expect("8 bytes")))
+    }
+}
+
+impl<const N: usize> Encode for [u8; N] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+}
+
+impl<const N: usize> Decode for [u8; N] {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(input.read_bytes(N)?.try_into().expect("N bytes"))
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Decode for String {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        let len = input.read_len()?;
+        let bytes = input.read_bytes(len)?;
+
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::ValueInvalid { kind: "string" })
+    }
+}
+
+impl Encode for GUID {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_bytes_be());
+    }
+}
+
+impl Decode for GUID {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(GUID::from_bytes_be(input.read_bytes(GUID_BYTES)?))
+    }
+}
+
+impl Encode for Time {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.as_micros().encode(out);
+    }
+}
+
+impl Decode for Time {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Time::from_micros(u64::decode(input)?))
+    }
+}
+
+impl Encode for Address {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.index() as u32).encode(out);
+    }
+}
+
+impl Decode for Address {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Address::new(u32::decode(input)?))
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => write_tag(out, 0),
+            Some(value) => {
+                write_tag(out, 1);
+                value.encode(out);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        match input.read_u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(input)?)),
+            tag => Err(DecodeError::TagInvalid {
+                kind: "option",
+                tag,
+            }),
+        }
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok((A::decode(input)?, B::decode(input)?))
+    }
+}
+
+/// Encodes the length of `items`, then each of them.
+fn encode_all<'a, T: Encode + 'a>(
+    out: &mut Vec<u8>,
+    len: usize,
+    items: impl IntoIterator<Item = &'a T>,
+) {
+    len.encode(out);
+    for item in items {
+        item.encode(out);
+    }
+}
+
+/// Decodes a length, then that many items.
+fn decode_all<T: Decode, C: FromIterator<T>>(input: &mut Decoder<'_>) -> Result<C, DecodeError> {
+    let len = input.read_len()?;
+    (0..len).map(|_| T::decode(input)).collect()
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_all(out, self.len(), self);
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        decode_all(input)
+    }
+}
+
+impl<T: Encode> Encode for VecDeque<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_all(out, self.len(), self);
+    }
+}
+
+impl<T: Decode> Decode for VecDeque<T> {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        decode_all(input)
+    }
+}
+
+impl<T: Encode + Hash + Eq> Encode for IndexSet<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_all(out, self.len(), self);
+    }
+}
+
+impl<T: Decode + Hash + Eq> Decode for IndexSet<T> {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        decode_all(input)
+    }
+}
+
+impl<K: Encode + Hash + Eq, V: Encode> Encode for IndexMap<K, V> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        for (key, value) in self {
+            key.encode(out);
+            value.encode(out);
+        }
+    }
+}
+
+impl<K: Decode + Hash + Eq, V: Decode> Decode for IndexMap<K, V> {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        decode_all(input)
+    }
+}
+
+impl<K: Encode + Ord, V: Encode> Encode for BTreeMap<K, V> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        for (key, value) in self {
+            key.encode(out);
+            value.encode(out);
+        }
+    }
+}
+
+impl<K: Decode + Ord, V: Decode> Decode for BTreeMap<K, V> {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        decode_all(input)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use indexmap::IndexMap;
+
+    use crate::{primitives::GUID, simulator::Time};
+
+    use super::{Decode, DecodeError, Encode};
+
+    #[test]
+    fn varints() {
+        assert_eq!(0u64.to_bytes(), [0]);
+        assert_eq!(127u64.to_bytes(), [0x7f]);
+        assert_eq!(300u64.to_bytes(), [0xac, 0x02]);
+
+        for value in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            assert_eq!(u64::from_bytes(&value.to_bytes()), Ok(value));
+        }
+
+        assert_eq!(
+            u8::decode(&mut super::Decoder::new(&[])),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            u32::from_bytes(&u64::MAX.to_bytes()),
+            Err(DecodeError::IntegerOverflow)
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let guid = GUID::from(0x1234u16);
+        assert_eq!(guid.to_bytes().len(), 20);
+        assert_eq!(GUID::from_bytes(&guid.to_bytes()), Ok(guid));
+
+        let map = [(guid, vec![Some(Time::from_secs(3)), None])]
+            .into_iter()
+            .collect::<IndexMap<_, _>>();
+        assert_eq!(IndexMap::from_bytes(&map.to_bytes()), Ok(map));
+
+        let mut bytes = true.to_bytes();
+        bytes.push(0);
+        assert_eq!(
+            bool::from_bytes(&bytes),
+            Err(DecodeError::TrailingBytes { count: 1 })
+        );
+        assert_eq!(
+            bool::from_bytes(&[2]),
+            Err(DecodeError::TagInvalid {
+                kind: "bool",
+                tag: 2
+            })
+        );
+    }
+}
@@ -0,0 +1,7 @@
+pub mod codec;
+pub mod network;
+pub mod node;
+pub mod primitives;
+pub mod scenario;
+pub mod simulator;
+pub mod topology;
@@ -1,6 +1,336 @@
-pub mod node;
-pub mod primitives;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Instant,
+};
 
-fn main() {
-    println!("Hello, world!");
+use clap::{Args, Parser, Subcommand};
+use rand::Rng;
+
+use p2p_simulator::{
+    network::Network,
+    node::Operation,
+    primitives::GUID,
+    scenario::{IdDistribution, Scenario},
+    simulator::{Histogram, Simulator, Tracer},
+    topology::Overlay,
+};
+
+/// Discrete-event simulator for Kademlia networks.
+///
+/// Exits with 1 if a scenario assertion fails, and 2 on any other error.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    #[command(flatten)]
+    overrides: Overrides,
+}
+
+/// Parameters which take precedence over those of a scenario.
+#[derive(Args)]
+struct Overrides {
+    /// Seed every random draw is taken from.
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+    /// Number of initial nodes.
+    #[arg(long, global = true)]
+    nodes: Option<usize>,
+    /// Replication factor.
+    #[arg(short, global = true)]
+    k: Option<usize>,
+    /// Number of queries a lookup keeps in flight.
+    #[arg(long, global = true)]
+    alpha: Option<usize>,
+    /// Simulated time, in seconds.
+    #[arg(long, global = true)]
+    duration: Option<f64>,
+    /// Directory results are written to.
+    #[arg(long, short, global = true)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Runs a TOML or JSON scenario and checks its assertions.
+    Run {
+        scenario: PathBuf,
+        /// Writes every event of the run to trace.jsonl in the output
+        /// directory.
+        #[arg(long)]
+        trace: bool,
+        /// Writes the final overlay graph to overlay.dot and overlay.graphml
+        /// in the output directory.
+        #[arg(long)]
+        overlay: bool,
+        /// Saves the state of the simulator at the end of the run to this
+        /// file, see `inspect`.
+        #[arg(long)]
+        snapshot: Option<PathBuf>,
+    },
+    /// Prints the GUIDs the initial nodes of a network would get.
+    GenerateIds {
+        /// Clusters the GUIDs under a common prefix of this many bits.
+        #[arg(long)]
+        clustered: Option<u32>,
+        /// Numbers the GUIDs sequentially instead.
+        #[arg(long, conflicts_with = "clustered")]
+        sequential: bool,
+    },
+    /// Describes the simulator state saved in a snapshot.
+    Inspect { snapshot: PathBuf },
+    /// Measures how fast lookups are simulated.
+    Bench {
+        /// Number of lookups to run.
+        #[arg(long, default_value_t = 1_000)]
+        lookups: usize,
+    },
+}
+
+impl Overrides {
+    fn apply(&self, scenario: &mut Scenario) {
+        if let Some(seed) = self.seed {
+            scenario.seed = seed;
+        }
+        if let Some(nodes) = self.nodes {
+            scenario.nodes = nodes;
+        }
+        if let Some(k) = self.k {
+            scenario.k = k;
+        }
+        if let Some(alpha) = self.alpha {
+            scenario.alpha = alpha;
+        }
+        if let Some(duration) = self.duration {
+            scenario.duration = Some(duration);
+        }
+    }
+}
+
+type Error = Box<dyn std::error::Error>;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = match &cli.command {
+        Command::Run {
+            scenario,
+            trace,
+            overlay,
+            snapshot,
+        } => run(
+            scenario,
+            *trace,
+            *overlay,
+            snapshot.as_deref(),
+            &cli.overrides,
+        ),
+        Command::GenerateIds {
+            clustered,
+            sequential,
+        } => {
+            let ids = match (clustered, sequential) {
+                (Some(bits), _) => IdDistribution::Clustered { bits: *bits },
+                (None, true) => IdDistribution::Sequential,
+                (None, false) => IdDistribution::Uniform,
+            };
+            generate_ids(ids, &cli.overrides).map(|_| true)
+        }
+        Command::Inspect { snapshot } => inspect(snapshot).map(|_| true),
+        Command::Bench { lookups } => bench(*lookups, &cli.overrides).map(|_| true),
+    };
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Runs a scenario, returning whether all its assertions hold.
+fn run(
+    path: &Path,
+    trace: bool,
+    overlay: bool,
+    snapshot: Option<&Path>,
+    overrides: &Overrides,
+) -> Result<bool, Error> {
+    let mut scenario = Scenario::from_file(path)?;
+    overrides.apply(&mut scenario);
+    let output = overrides.output.as_deref().unwrap_or(Path::new("."));
+
+    let mut sim = scenario.build()?;
+    if trace {
+        std::fs::create_dir_all(output)?;
+        sim.trace(Tracer::to_file(output.join("trace.jsonl"))?);
+    }
+
+    scenario.play(&mut sim);
+    sim.finish_trace()?;
+
+    if let Some(snapshot) = snapshot {
+        sim.save(snapshot)?;
+    }
+
+    if overlay {
+        std::fs::create_dir_all(output)?;
+        let overlay = Overlay::new(sim.network());
+
+        let mut file = BufWriter::new(File::create(output.join("overlay.dot"))?);
+        overlay.write_dot(&mut file)?;
+        file.flush()?;
+
+        let mut file = BufWriter::new(File::create(output.join("overlay.graphml"))?);
+        overlay.write_graphml(&mut file)?;
+        file.flush()?;
+    }
+
+    println!(
+        "Simulated {} with {} nodes, {} events processed",
+        sim.now(),
+        sim.network().len(),
+        sim.processed()
+    );
+
+    let report = sim.metrics().report();
+    print!("{report}");
+
+    if let Some(output) = &overrides.output {
+        std::fs::create_dir_all(output)?;
+        write_operations(&output.join("operations.csv"), &sim)?;
+
+        let file = BufWriter::new(File::create(output.join("metrics.json"))?);
+        serde_json::to_writer_pretty(file, &report)?;
+    }
+
+    let failures = scenario.check(&sim)?;
+    for failure in failures.iter() {
+        eprintln!("Assertion {failure}");
+    }
+
+    Ok(failures.is_empty())
+}
+
+/// Writes every completed operation as a CSV row.
+fn write_operations(path: &Path, sim: &Simulator) -> Result<(), Error> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(
+        file,
+        "started_us,finished_us,address,operation,target,messages,hops,failed,found"
+    )?;
+
+    for result in sim.completed() {
+        let target = result
+            .operation
+            .target()
+            .map(|target| target.to_hex())
+            .unwrap_or_default();
+
+        writeln!(
+            file,
+            "{},{},{},{},{},{},{},{},{}",
+            result.started.as_micros(),
+            result.finished.as_micros(),
+            result.address.index(),
+            result.operation.kind(),
+            target,
+            result.messages,
+            result.lookup.hops,
+            result.lookup.failed.len(),
+            result.lookup.value.is_some(),
+        )?;
+    }
+
+    Ok(file.flush()?)
+}
+
+fn generate_ids(ids: IdDistribution, overrides: &Overrides) -> Result<(), Error> {
+    ids.validate()?;
+
+    let mut network = Network::new(overrides.seed.unwrap_or_default());
+    let count = overrides.nodes.unwrap_or(1);
+    let mut out = BufWriter::new(std::io::stdout().lock());
+
+    for contact in ids.spawn(&mut network, count) {
+        writeln!(out, "{}", contact.guid.to_hex())?;
+    }
+
+    Ok(out.flush()?)
+}
+
+fn inspect(snapshot: &Path) -> Result<(), Error> {
+    let sim = Simulator::load(snapshot)?;
+    let network = sim.network();
+
+    let contacts = network
+        .nodes()
+        .map(|node| node.peers().len() as u64)
+        .collect::<Histogram>()
+        .summary();
+    let values = network
+        .nodes()
+        .map(|node| node.storage().len() as u64)
+        .sum::<u64>();
+
+    println!("time              {}", sim.now());
+    println!("seed              {}", network.seed());
+    println!("nodes             {}", network.len());
+    println!("pending events    {}", sim.pending());
+    println!("processed events  {}", sim.processed());
+    println!("stored values     {values}");
+    println!("lost keys         {}", sim.lost_keys().len());
+    if let Some(contacts) = contacts {
+        println!("contacts per node {contacts}");
+    }
+    if sim.partition().is_some() {
+        println!("network is partitioned");
+    }
+
+    Ok(())
+}
+
+fn bench(lookups: usize, overrides: &Overrides) -> Result<(), Error> {
+    let mut scenario = Scenario::new(1_000);
+    overrides.apply(&mut scenario);
+
+    let setup = Instant::now();
+    let mut sim = scenario.build()?;
+    let setup = setup.elapsed();
+
+    for _ in 0..lookups {
+        let network = sim.network_mut();
+        let Some(contact) = network.random_contact() else {
+            break;
+        };
+        let target = network.rng().gen::<GUID>();
+
+        sim.start(contact.address, Operation::FindNode { target });
+    }
+
+    let start = Instant::now();
+    // Stops at `--duration` if set, even with lookups still running
+    scenario.play(&mut sim);
+    let elapsed = start.elapsed().as_secs_f64();
+
+    println!(
+        "{} nodes set up in {:.3}s",
+        scenario.nodes,
+        setup.as_secs_f64()
+    );
+    println!(
+        "{} lookups, {} events in {:.3}s: {:.0} lookups/s, {:.0} events/s",
+        sim.completed().len(),
+        sim.processed(),
+        elapsed,
+        sim.completed().len() as f64 / elapsed,
+        sim.processed() as f64 / elapsed,
+    );
+
+    Ok(())
 }
@@ -0,0 +1,303 @@
+use indexmap::IndexMap;
+use rand::Rng;
+
+use crate::{
+    codec::{Decode, DecodeError, Decoder, Encode},
+    node::{BucketUpdate, Contact, Node},
+    primitives::GUID,
+    simulator::SimRng,
+};
+
+/// Location of a node in a [`Network`].
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Address(u32);
+
+impl Address {
+    pub fn new(index: u32) -> Self {
+        Self(index)
+    }
+
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "@{}", self.0)
+    }
+}
+
+/// Arena owning every simulated [`Node`].
+///
+/// Nodes only ever refer to each other through [`Contact`]s, which the
+/// network resolves back to the node they point to. Addresses are never
+/// reused: once a node is removed its slot stays empty, so stale contacts
+/// fail to resolve instead of pointing to another node.
+///
+/// The network also owns the [`SimRng`] every random draw of a simulation is
+/// taken from, so a whole run is determined by the seed it was created with.
+pub struct Network {
+    nodes: Vec<Option<Node>>,
+    addresses: IndexMap<GUID, Address>,
+    rng: SimRng,
+}
+
+impl Network {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            nodes: Vec::new(),
+            addresses: IndexMap::new(),
+            rng: SimRng::new(seed),
+        }
+    }
+
+    pub fn rng(&mut self) -> &mut SimRng {
+        &mut self.rng
+    }
+
+    /// Seed the network was created with.
+    pub fn seed(&self) -> u64 {
+        self.rng.seed()
+    }
+
+    /// Creates a new node and adds it to the network.
+    pub fn spawn(&mut self, name: &str) -> Contact {
+        self.spawn_with_peers(name, &[])
+    }
+
+    /// Creates a new node which already knows of `peers`, and adds it to the
+    /// network.
+    pub fn spawn_with_peers(&mut self, name: &str, peers: &[Contact]) -> Contact {
+        let address = Address::new(self.nodes.len() as u32);
+
+        loop {
+            // Every attempt draws a new salt, so a GUID collision is not
+            // repeated
+            let node = Node::new_with_peers(name, address, peers, &mut self.rng);
+            if let Some(contact) = self.insert(node) {
+                return contact;
+            }
+        }
+    }
+
+    /// Like [`Network::spawn_with_peers`], but the node is given `guid`
+    /// rather than one derived from a name. Returns `None` if a node with
+    /// this GUID is already part of the network.
+    pub fn spawn_with_guid(&mut self, guid: GUID, peers: &[Contact]) -> Option<Contact> {
+        let address = Address::new(self.nodes.len() as u32);
+        self.insert(Node::with_guid(guid, address).with_peers(peers))
+    }
+
+    /// Adds `node` to the network, unless its GUID is already taken.
+    fn insert(&mut self, node: Node) -> Option<Contact> {
+        let contact = node.contact();
+
+        if self.addresses.contains_key(&contact.guid) {
+            return None;
+        }
+        self.addresses.insert(contact.guid, contact.address);
+        self.nodes.push(Some(node));
+
+        Some(contact)
+    }
+
+    /// Removes a node from the network. Contacts pointing to it will no
+    /// longer resolve.
+    pub fn remove(&mut self, address: Address) -> Option<Node> {
+        let node = self.nodes.get_mut(address.index())?.take()?;
+        self.addresses.swap_remove(&node.guid());
+        Some(node)
+    }
+
+    pub fn get(&self, address: Address) -> Option<&Node> {
+        self.nodes.get(address.index())?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, address: Address) -> Option<&mut Node> {
+        self.nodes.get_mut(address.index())?.as_mut()
+    }
+
+    /// Resolves a contact to the node it points to, provided it is still
+    /// part of the network under the same GUID.
+    pub fn resolve(&self, contact: &Contact) -> Option<&Node> {
+        self.get(contact.address)
+            .filter(|node| node.guid() == contact.guid)
+    }
+
+    pub fn resolve_mut(&mut self, contact: &Contact) -> Option<&mut Node> {
+        self.get_mut(contact.address)
+            .filter(|node| node.guid() == contact.guid)
+    }
+
+    /// Contact of the node with the given GUID, if it is part of the network.
+    pub fn contact(&self, guid: &GUID) -> Option<Contact> {
+        self.addresses
+            .get(guid)
+            .map(|address| Contact::new(*guid, *address))
+    }
+
+    /// Contact of a node picked uniformly at random, if there are any.
+    pub fn random_contact(&mut self) -> Option<Contact> {
+        if self.addresses.is_empty() {
+            return None;
+        }
+
+        let i = self.rng.gen_range(0..self.addresses.len());
+        let (guid, address) = self.addresses.get_index(i)?;
+
+        Some(Contact::new(*guid, *address))
+    }
+
+    /// Whether `contact` would answer a ping.
+    pub fn ping(&self, contact: &Contact) -> bool {
+        self.resolve(contact).is_some()
+    }
+
+    /// Runs `f` on the node at `address` while still giving it access to the
+    /// rest of the network. The node itself cannot be resolved for the
+    /// duration of the call.
+    pub fn with_node<R>(
+        &mut self,
+        address: Address,
+        f: impl FnOnce(&mut Node, &mut Network) -> R,
+    ) -> Option<R> {
+        let mut node = self.nodes.get_mut(address.index())?.take()?;
+        let result = f(&mut node, self);
+        self.nodes[address.index()] = Some(node);
+
+        Some(result)
+    }
+
+    /// Makes the node at `address` aware of `contact`.
+    pub fn connect(&mut self, address: Address, contact: Contact) -> Option<BucketUpdate> {
+        self.with_node(address, |node, network| node.add_peer(network, contact))
+            .flatten()
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.iter().flatten()
+    }
+
+    pub fn nodes_mut(&mut self) -> impl Iterator<Item = &mut Node> {
+        self.nodes.iter_mut().flatten()
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+}
+
+impl Encode for Network {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.nodes.encode(out);
+        self.addresses.encode(out);
+        self.rng.encode(out);
+    }
+}
+
+impl Decode for Network {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            nodes: Vec::decode(input)?,
+            addresses: IndexMap::decode(input)?,
+            rng: SimRng::decode(input)?,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{node::Node, primitives::GUID};
+
+    use super::{Address, Network};
+
+    #[test]
+    fn spawn_and_resolve() {
+        let mut network = Network::new(0);
+        let contact_a = network.spawn("a");
+        let contact_b = network.spawn_with_peers("b", &[contact_a]);
+
+        assert_eq!(network.len(), 2);
+        assert_eq!(network.contact(&contact_a.guid), Some(contact_a));
+        assert_eq!(
+            network.resolve(&contact_a).map(|n| n.contact()),
+            Some(contact_a)
+        );
+
+        let node_b = network.resolve(&contact_b).unwrap();
+        assert!(node_b.peers().contains(&contact_a.guid));
+    }
+
+    #[test]
+    fn remove() {
+        let mut network = Network::new(0);
+        let contact_a = network.spawn("a");
+        let contact_b = network.spawn("b");
+
+        assert!(network.ping(&contact_a));
+        assert!(network.remove(contact_a.address).is_some());
+        assert!(!network.ping(&contact_a));
+        assert!(network.contact(&contact_a.guid).is_none());
+        assert!(network.ping(&contact_b));
+        assert_eq!(network.len(), 1);
+
+        let contact_c = network.spawn("c");
+        assert_eq!(contact_c.address, Address::new(2));
+    }
+
+    #[test]
+    fn duplicate_guid() {
+        let mut network = Network::new(0);
+        let guid = GUID::from(42u8);
+        let contact = network.spawn_with_guid(guid, &[]).unwrap();
+
+        assert_eq!(network.spawn_with_guid(guid, &[]), None);
+        assert_eq!(network.len(), 1);
+        assert_eq!(network.contact(&guid), Some(contact));
+    }
+
+    #[test]
+    fn insert_keeps_existing_guid() {
+        let mut network = Network::new(0);
+        let contact = network.spawn("a");
+        let twin = Node::with_guid(contact.guid, Address::new(1));
+
+        assert_eq!(network.insert(twin), None);
+        assert_eq!(network.contact(&contact.guid), Some(contact));
+        assert_eq!(network.len(), 1);
+        assert!(network.get(Address::new(1)).is_none());
+    }
+
+    #[test]
+    fn seeded_guids() {
+        let mut network_a = Network::new(42);
+        let mut network_b = Network::new(42);
+        let mut network_c = Network::new(43);
+
+        let contact_a = network_a.spawn("node");
+        let contact_b = network_b.spawn("node");
+        let contact_c = network_c.spawn("node");
+
+        assert_eq!(contact_a, contact_b);
+        assert_ne!(contact_a.guid, contact_c.guid);
+    }
+
+    #[test]
+    fn connect_is_shared() {
+        let mut network = Network::new(0);
+        let contact_a = network.spawn("a");
+        let contact_b = network.spawn("b");
+
+        assert!(network.connect(contact_a.address, contact_b).is_some());
+
+        let node_a = network.get(contact_a.address).unwrap();
+        assert_eq!(node_a.peers().get(&contact_b.guid), Some(&contact_b));
+        assert_eq!(network.connect(contact_a.address, contact_a), None);
+    }
+}
@@ -0,0 +1,33 @@
+use crate::{
+    codec::{Decode, DecodeError, Decoder, Encode},
+    network::Address,
+    primitives::GUID,
+};
+
+/// Lightweight handle to a peer: its GUID, and the address at which the
+/// [`Network`](crate::network::Network) can resolve it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Contact {
+    pub guid: GUID,
+    pub address: Address,
+}
+
+impl Contact {
+    pub fn new(guid: GUID, address: Address) -> Self {
+        Self { guid, address }
+    }
+}
+
+impl Encode for Contact {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.guid.encode(out);
+        self.address.encode(out);
+    }
+}
+
+impl Decode for Contact {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self::new(GUID::decode(input)?, Address::decode(input)?))
+    }
+}
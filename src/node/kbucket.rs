@@ -0,0 +1,398 @@
+use std::collections::VecDeque;
+
+use crate::{
+    codec::{Decode, DecodeError, Decoder, Encode},
+    primitives::GUID,
+};
+
+use super::Contact;
+
+/// Outcome of [`KBucket::update`] and [`KBucket::insert`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BucketUpdate {
+    /// The contact was not known and there was room for it in the bucket.
+    Inserted,
+    /// The contact was already in the bucket and has been moved to the tail.
+    Refreshed,
+    /// The bucket was full and its head did not respond: the head has been
+    /// evicted in favour of the new contact.
+    Evicted { evicted: Contact },
+    /// The bucket was full and its head responded: the new contact has been
+    /// placed in the replacement cache instead.
+    Cached,
+    /// The bucket was full: the new contact has been placed in the
+    /// replacement cache until `head` is pinged. See [`KBucket::insert`].
+    Full { head: Contact },
+}
+
+/// A Kademlia k-bucket, holding up to `K` contacts ordered from least to most
+/// recently seen.
+///
+/// When the bucket is full, new contacts are only let in if the least recently
+/// seen contact fails to respond to a ping. Otherwise they are kept in a
+/// replacement cache, from which they are promoted once a contact is removed.
+/// This favours long-lived contacts, which is what makes Kademlia resistant to
+/// churn.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "UncheckedKBucket<K>"))]
+pub struct KBucket<const K: usize> {
+    contacts: VecDeque<Contact>,
+    replacements: VecDeque<Contact>,
+}
+
+impl<const K: usize> Default for KBucket<K> {
+    fn default() -> Self {
+        Self {
+            contacts: VecDeque::new(),
+            replacements: VecDeque::new(),
+        }
+    }
+}
+
+impl<const K: usize> KBucket<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records activity from `contact`.
+    ///
+    /// If the bucket is full, `ping` is called on the least recently seen
+    /// contact (usually through `Node::ping`) to decide whether it should be
+    /// evicted.
+    pub fn update(
+        &mut self,
+        contact: Contact,
+        ping: impl FnOnce(&Contact) -> bool,
+    ) -> BucketUpdate {
+        if let Some(i) = self.position(&contact.guid) {
+            self.contacts.remove(i);
+            self.contacts.push_back(contact);
+            return BucketUpdate::Refreshed;
+        }
+
+        if !self.is_full() {
+            self.contacts.push_back(contact);
+            return BucketUpdate::Inserted;
+        }
+
+        let head = self
+            .contacts
+            .pop_front()
+            .expect("Full bucket cannot be empty");
+
+        if ping(&head) {
+            self.contacts.push_back(head);
+            self.cache(contact);
+            BucketUpdate::Cached
+        } else {
+            self.contacts.push_back(contact);
+            BucketUpdate::Evicted { evicted: head }
+        }
+    }
+
+    /// Records activity from `contact` without pinging anyone.
+    ///
+    /// If the bucket is full, the contact is cached and the least recently
+    /// seen contact is returned in [`BucketUpdate::Full`]. It is then up to
+    /// the caller to ping it and either [`KBucket::update`] it if it
+    /// responds, which moves it to the tail, or [`KBucket::remove`] it if it
+    /// does not, which promotes the cached contact.
+    pub fn insert(&mut self, contact: Contact) -> BucketUpdate {
+        if let Some(i) = self.position(&contact.guid) {
+            self.contacts.remove(i);
+            self.contacts.push_back(contact);
+            return BucketUpdate::Refreshed;
+        }
+
+        if !self.is_full() {
+            self.contacts.push_back(contact);
+            return BucketUpdate::Inserted;
+        }
+
+        self.cache(contact);
+        let head = *self.head().expect("Full bucket cannot be empty");
+
+        BucketUpdate::Full { head }
+    }
+
+    /// Removes `guid` from the bucket, promoting the most recently seen
+    /// replacement in its place. Returns `false` if `guid` was not in the
+    /// bucket.
+    pub fn remove(&mut self, guid: &GUID) -> bool {
+        match self.position(guid) {
+            Some(i) => {
+                self.contacts.remove(i);
+                if let Some(replacement) = self.replacements.pop_back() {
+                    self.contacts.push_back(replacement);
+                }
+                true
+            }
+            None => {
+                self.replacements.retain(|r| r.guid != *guid);
+                false
+            }
+        }
+    }
+
+    pub fn contains(&self, guid: &GUID) -> bool {
+        self.position(guid).is_some()
+    }
+
+    /// Least recently seen contact.
+    pub fn head(&self) -> Option<&Contact> {
+        self.contacts.front()
+    }
+
+    /// Contacts, from least to most recently seen.
+    pub fn contacts(&self) -> impl Iterator<Item = &Contact> {
+        self.contacts.iter()
+    }
+
+    /// Replacement cache, from least to most recently seen.
+    pub fn replacements(&self) -> impl Iterator<Item = &Contact> {
+        self.replacements.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.contacts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contacts.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.contacts.len() >= K
+    }
+
+    fn position(&self, guid: &GUID) -> Option<usize> {
+        self.contacts.iter().position(|c| c.guid == *guid)
+    }
+
+    /// Rejects buckets holding more than `K` contacts or replacements,
+    /// which no sequence of updates can produce.
+    fn checked(self) -> Result<Self, DecodeError> {
+        if self.contacts.len() > K || self.replacements.len() > K {
+            return Err(DecodeError::ValueInvalid { kind: "k-bucket" });
+        }
+
+        Ok(self)
+    }
+
+    fn cache(&mut self, contact: Contact) {
+        self.replacements.retain(|r| r.guid != contact.guid);
+        if self.replacements.len() >= K {
+            self.replacements.pop_front();
+        }
+        self.replacements.push_back(contact);
+    }
+}
+
+impl<const K: usize> Encode for KBucket<K> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.contacts.encode(out);
+        self.replacements.encode(out);
+    }
+}
+
+impl<const K: usize> Decode for KBucket<K> {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Self {
+            contacts: VecDeque::decode(input)?,
+            replacements: VecDeque::decode(input)?,
+        }
+        .checked()
+    }
+}
+
+/// [`KBucket`] as deserialized, before it is checked.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct UncheckedKBucket<const K: usize> {
+    contacts: VecDeque<Contact>,
+    replacements: VecDeque<Contact>,
+}
+
+#[cfg(feature = "serde")]
+impl<const K: usize> TryFrom<UncheckedKBucket<K>> for KBucket<K> {
+    type Error = DecodeError;
+
+    fn try_from(bucket: UncheckedKBucket<K>) -> Result<Self, DecodeError> {
+        Self {
+            contacts: bucket.contacts,
+            replacements: bucket.replacements,
+        }
+        .checked()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        codec::{Decode, DecodeError, Encode},
+        network::Address,
+        node::Contact,
+        primitives::GUID,
+    };
+
+    use super::{BucketUpdate, KBucket};
+
+    fn contacts(n: u32) -> Vec<Contact> {
+        (0..n)
+            .map(|i| Contact::new(GUID::from(i), Address::new(i)))
+            .collect()
+    }
+
+    #[test]
+    fn insert() {
+        let mut bucket = KBucket::<4>::new();
+
+        for contact in contacts(4) {
+            assert_eq!(
+                bucket.update(contact, |_| unreachable!()),
+                BucketUpdate::Inserted
+            );
+        }
+
+        assert!(bucket.is_full());
+        assert_eq!(bucket.contacts().copied().collect::<Vec<_>>(), contacts(4));
+    }
+
+    #[test]
+    fn refresh_moves_to_tail() {
+        let mut bucket = KBucket::<4>::new();
+        let contacts = contacts(3);
+
+        for contact in contacts.iter() {
+            bucket.update(*contact, |_| unreachable!());
+        }
+
+        assert_eq!(
+            bucket.update(contacts[0], |_| unreachable!()),
+            BucketUpdate::Refreshed
+        );
+        assert_eq!(bucket.head(), Some(&contacts[1]));
+        assert_eq!(bucket.contacts().last(), Some(&contacts[0]));
+        assert_eq!(bucket.len(), 3);
+    }
+
+    #[test]
+    fn full_head_responsive() {
+        let mut bucket = KBucket::<2>::new();
+        let contacts = contacts(3);
+
+        bucket.update(contacts[0], |_| unreachable!());
+        bucket.update(contacts[1], |_| unreachable!());
+
+        let update = bucket.update(contacts[2], |head| {
+            assert_eq!(*head, contacts[0]);
+            true
+        });
+
+        assert_eq!(update, BucketUpdate::Cached);
+        assert_eq!(
+            bucket.contacts().copied().collect::<Vec<_>>(),
+            [contacts[1], contacts[0]]
+        );
+        assert_eq!(
+            bucket.replacements().copied().collect::<Vec<_>>(),
+            [contacts[2]]
+        );
+    }
+
+    #[test]
+    fn full_head_unresponsive() {
+        let mut bucket = KBucket::<2>::new();
+        let contacts = contacts(3);
+
+        bucket.update(contacts[0], |_| unreachable!());
+        bucket.update(contacts[1], |_| unreachable!());
+
+        let update = bucket.update(contacts[2], |_| false);
+
+        assert_eq!(
+            update,
+            BucketUpdate::Evicted {
+                evicted: contacts[0]
+            }
+        );
+        assert_eq!(
+            bucket.contacts().copied().collect::<Vec<_>>(),
+            [contacts[1], contacts[2]]
+        );
+        assert_eq!(bucket.replacements().count(), 0);
+    }
+
+    #[test]
+    fn remove_promotes_replacement() {
+        let mut bucket = KBucket::<2>::new();
+        let contacts = contacts(3);
+
+        bucket.update(contacts[0], |_| unreachable!());
+        bucket.update(contacts[1], |_| unreachable!());
+        bucket.update(contacts[2], |_| true);
+
+        assert!(bucket.remove(&contacts[1].guid));
+        assert!(bucket.contains(&contacts[2].guid));
+        assert_eq!(bucket.replacements().count(), 0);
+        assert!(!bucket.remove(&contacts[1].guid));
+    }
+
+    #[test]
+    fn insert_full_defers_to_caller() {
+        let mut bucket = KBucket::<2>::new();
+        let contacts = contacts(3);
+
+        bucket.insert(contacts[0]);
+        bucket.insert(contacts[1]);
+
+        assert_eq!(
+            bucket.insert(contacts[2]),
+            BucketUpdate::Full { head: contacts[0] }
+        );
+        assert_eq!(bucket.head(), Some(&contacts[0]));
+        assert_eq!(
+            bucket.replacements().copied().collect::<Vec<_>>(),
+            [contacts[2]]
+        );
+
+        // Head did not answer the ping
+        assert!(bucket.remove(&contacts[0].guid));
+        assert_eq!(
+            bucket.contacts().copied().collect::<Vec<_>>(),
+            [contacts[1], contacts[2]]
+        );
+    }
+
+    #[test]
+    fn replacement_cache_bounded() {
+        let mut bucket = KBucket::<1>::new();
+        let contacts = contacts(4);
+
+        bucket.update(contacts[0], |_| unreachable!());
+        bucket.update(contacts[1], |_| true);
+        bucket.update(contacts[2], |_| true);
+        bucket.update(contacts[3], |_| true);
+
+        assert_eq!(
+            bucket.replacements().copied().collect::<Vec<_>>(),
+            [contacts[3]]
+        );
+    }
+
+    #[test]
+    fn decode_checks_size() {
+        let mut bucket = KBucket::<4>::new();
+        for contact in contacts(3) {
+            bucket.update(contact, |_| unreachable!());
+        }
+
+        let bytes = bucket.to_bytes();
+        assert_eq!(KBucket::<3>::from_bytes(&bytes).unwrap().len(), 3);
+        assert_eq!(
+            KBucket::<2>::from_bytes(&bytes).unwrap_err(),
+            DecodeError::ValueInvalid { kind: "k-bucket" }
+        );
+    }
+}
@@ -0,0 +1,578 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    codec::{write_tag, Decode, DecodeError, Decoder, Encode},
+    primitives::GUID,
+};
+
+use super::{ConnectionStep, Contact, DATA, K};
+
+/// Parameters of an iterative [`Lookup`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LookupConfig {
+    /// Maximum number of queries in flight at any time.
+    pub alpha: usize,
+    /// Number of closest contacts the lookup is trying to find.
+    pub k: usize,
+}
+
+impl Default for LookupConfig {
+    fn default() -> Self {
+        Self { alpha: 3, k: K }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum CandidateState {
+    NotQueried,
+    Pending,
+    Responded,
+    /// Responded with the value being looked up.
+    Found,
+    Failed,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Candidate {
+    contact: Contact,
+    state: CandidateState,
+    hop: usize,
+}
+
+/// Outcome of a [`Lookup`], for analysis.
+#[derive(Clone, Debug, Default)]
+pub struct LookupResult {
+    pub target: GUID,
+    /// Up to `k` closest contacts which responded, by increasing distance.
+    pub closest: Vec<Contact>,
+    /// Length of the longest chain of responses the lookup followed.
+    pub hops: usize,
+    /// Every contact which was queried, in the order it was queried.
+    pub contacted: Vec<Contact>,
+    /// Contacts which failed to respond.
+    pub failed: Vec<Contact>,
+    /// Value returned by a FIND_VALUE lookup, if any.
+    pub value: Option<Vec<DATA>>,
+}
+
+/// Iterative Kademlia lookup.
+///
+/// The lookup does not send anything itself: [`Lookup::poll`] returns
+/// [`ConnectionStep::Seeking`] for each contact which should be queried next,
+/// and the caller reports back with [`Lookup::on_response`] or
+/// [`Lookup::on_failure`]. This lets the same lookup be driven by direct calls
+/// or by a simulated network.
+///
+/// The lookup keeps at most `alpha` queries in flight, always picking the
+/// closest contacts which have not been queried yet, and is done once the `k`
+/// closest contacts it knows of have all responded. For FIND_VALUE lookups,
+/// it stops as soon as any contact returns the value through
+/// [`Lookup::on_value`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "UncheckedLookup"))]
+pub struct Lookup {
+    local: GUID,
+    target: GUID,
+    config: LookupConfig,
+    candidates: BTreeMap<GUID, Candidate>,
+    contacted: Vec<Contact>,
+    value: Option<Vec<DATA>>,
+    in_flight: usize,
+    done: bool,
+}
+
+impl Lookup {
+    /// Starts a lookup for `target` from the node `local`, using `seeds` as
+    /// the initial candidates.
+    pub fn new(
+        local: GUID,
+        target: GUID,
+        config: LookupConfig,
+        seeds: impl IntoIterator<Item = Contact>,
+    ) -> Self {
+        let mut lookup = Self {
+            local,
+            target,
+            config,
+            candidates: BTreeMap::new(),
+            contacted: Vec::new(),
+            value: None,
+            in_flight: 0,
+            done: false,
+        };
+
+        for seed in seeds {
+            lookup.insert(seed, 1);
+        }
+
+        lookup
+    }
+
+    pub fn target(&self) -> GUID {
+        self.target
+    }
+
+    pub fn config(&self) -> LookupConfig {
+        self.config
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Contact of a candidate, such as one returned by
+    /// [`ConnectionStep::Seeking`].
+    pub fn contact(&self, id: &GUID) -> Option<&Contact> {
+        self.candidates
+            .get(&id.distance(&self.target))
+            .map(|candidate| &candidate.contact)
+    }
+
+    /// Advances the lookup.
+    ///
+    /// Returns [`ConnectionStep::Seeking`] with a contact to query,
+    /// [`ConnectionStep::Done`] once the lookup has completed, or `None` if
+    /// it is waiting on queries already in flight. `Done` holds the value
+    /// found by a FIND_VALUE lookup, and is empty otherwise.
+    pub fn poll(&mut self) -> Option<ConnectionStep> {
+        if self.done {
+            return Some(self.done_step());
+        }
+
+        let in_flight = self.in_flight;
+        let mut waiting = false;
+
+        for candidate in self
+            .candidates
+            .values_mut()
+            .filter(|candidate| candidate.state != CandidateState::Failed)
+            .take(self.config.k)
+        {
+            match candidate.state {
+                CandidateState::NotQueried if in_flight < self.config.alpha => {
+                    candidate.state = CandidateState::Pending;
+                    self.contacted.push(candidate.contact);
+                    self.in_flight += 1;
+
+                    return Some(ConnectionStep::Seeking {
+                        id: candidate.contact.guid,
+                    });
+                }
+                CandidateState::NotQueried | CandidateState::Pending => waiting = true,
+                CandidateState::Responded | CandidateState::Found | CandidateState::Failed => {}
+            }
+        }
+
+        if waiting {
+            None
+        } else {
+            self.done = true;
+            Some(self.done_step())
+        }
+    }
+
+    /// Records the contacts returned by `id` in response to a query.
+    pub fn on_response(&mut self, id: &GUID, contacts: &[Contact]) {
+        let Some(hop) = self.settle(id, CandidateState::Responded) else {
+            return;
+        };
+
+        for contact in contacts {
+            self.insert(*contact, hop + 1);
+        }
+    }
+
+    /// Records the value returned by `id` in response to a FIND_VALUE query,
+    /// which ends the lookup.
+    pub fn on_value(&mut self, id: &GUID, data: Vec<DATA>) {
+        if self.settle(id, CandidateState::Found).is_some() && self.value.is_none() {
+            self.value = Some(data);
+            self.done = true;
+        }
+    }
+
+    /// Records that `id` did not respond, as a [`ConnectionStep::Failed`].
+    /// Returns `false` if `id` was not waited on, for example because it
+    /// already responded or the lookup is done.
+    pub fn on_failure(&mut self, id: &GUID) -> bool {
+        self.settle(id, CandidateState::Failed).is_some()
+    }
+
+    /// Applies a step reported by whoever is driving the lookup. Only
+    /// [`ConnectionStep::Failed`] carries information for a node lookup.
+    pub fn on_step(&mut self, step: &ConnectionStep) {
+        if let ConnectionStep::Failed { id } = step {
+            self.on_failure(id);
+        }
+    }
+
+    pub fn result(&self) -> LookupResult {
+        let with_state = |state| {
+            self.candidates
+                .values()
+                .filter(move |candidate| candidate.state == state)
+        };
+
+        LookupResult {
+            target: self.target,
+            closest: with_state(CandidateState::Responded)
+                .take(self.config.k)
+                .map(|candidate| candidate.contact)
+                .collect(),
+            hops: with_state(CandidateState::Responded)
+                .chain(with_state(CandidateState::Found))
+                .map(|candidate| candidate.hop)
+                .max()
+                .unwrap_or_default(),
+            contacted: self.contacted.clone(),
+            failed: with_state(CandidateState::Failed)
+                .map(|candidate| candidate.contact)
+                .collect(),
+            value: self.value.clone(),
+        }
+    }
+
+    fn done_step(&self) -> ConnectionStep {
+        ConnectionStep::Done {
+            data: self.value.clone().unwrap_or_default(),
+        }
+    }
+
+    fn insert(&mut self, contact: Contact, hop: usize) {
+        if contact.guid == self.local {
+            return;
+        }
+
+        self.candidates
+            .entry(contact.guid.distance(&self.target))
+            .or_insert(Candidate {
+                contact,
+                state: CandidateState::NotQueried,
+                hop,
+            });
+    }
+
+    /// Marks a pending candidate as settled, returning its hop count. Once
+    /// the lookup is done, late responses are ignored so that its result no
+    /// longer changes.
+    fn settle(&mut self, id: &GUID, state: CandidateState) -> Option<usize> {
+        if self.done {
+            return None;
+        }
+
+        let candidate = self.candidates.get_mut(&id.distance(&self.target))?;
+
+        if candidate.state != CandidateState::Pending {
+            return None;
+        }
+
+        // Every pending candidate counts as in flight, see `Lookup::checked`
+        debug_assert!(self.in_flight > 0);
+        candidate.state = state;
+        self.in_flight -= 1;
+
+        Some(candidate.hop)
+    }
+
+    /// Rejects lookups whose count of queries in flight does not match their
+    /// pending candidates, which `settle` relies on.
+    fn checked(self) -> Result<Self, DecodeError> {
+        let pending = self
+            .candidates
+            .values()
+            .filter(|candidate| candidate.state == CandidateState::Pending)
+            .count();
+        if self.in_flight != pending {
+            return Err(DecodeError::ValueInvalid { kind: "lookup" });
+        }
+
+        Ok(self)
+    }
+}
+
+impl Encode for LookupConfig {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.alpha.encode(out);
+        self.k.encode(out);
+    }
+}
+
+impl Decode for LookupConfig {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            alpha: usize::decode(input)?,
+            k: usize::decode(input)?,
+        })
+    }
+}
+
+impl Encode for Candidate {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.contact.encode(out);
+        let state = match self.state {
+            CandidateState::NotQueried => 0,
+            CandidateState::Pending => 1,
+            CandidateState::Responded => 2,
+            CandidateState::Found => 3,
+            CandidateState::Failed => 4,
+        };
+        write_tag(out, state);
+        self.hop.encode(out);
+    }
+}
+
+impl Decode for Candidate {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        let contact = Contact::decode(input)?;
+        let state = match input.read_u8()? {
+            0 => CandidateState::NotQueried,
+            1 => CandidateState::Pending,
+            2 => CandidateState::Responded,
+            3 => CandidateState::Found,
+            4 => CandidateState::Failed,
+            tag => {
+                return Err(DecodeError::TagInvalid {
+                    kind: "candidate state",
+                    tag,
+                })
+            }
+        };
+
+        Ok(Self {
+            contact,
+            state,
+            hop: usize::decode(input)?,
+        })
+    }
+}
+
+impl Encode for Lookup {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.local.encode(out);
+        self.target.encode(out);
+        self.config.encode(out);
+        self.candidates.encode(out);
+        self.contacted.encode(out);
+        self.value.encode(out);
+        self.in_flight.encode(out);
+        self.done.encode(out);
+    }
+}
+
+impl Decode for Lookup {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Self {
+            local: GUID::decode(input)?,
+            target: GUID::decode(input)?,
+            config: LookupConfig::decode(input)?,
+            candidates: BTreeMap::decode(input)?,
+            contacted: Vec::decode(input)?,
+            value: Option::decode(input)?,
+            in_flight: usize::decode(input)?,
+            done: bool::decode(input)?,
+        }
+        .checked()
+    }
+}
+
+/// [`Lookup`] as deserialized, before it is checked.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct UncheckedLookup {
+    local: GUID,
+    target: GUID,
+    config: LookupConfig,
+    candidates: BTreeMap<GUID, Candidate>,
+    contacted: Vec<Contact>,
+    value: Option<Vec<DATA>>,
+    in_flight: usize,
+    done: bool,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<UncheckedLookup> for Lookup {
+    type Error = DecodeError;
+
+    fn try_from(lookup: UncheckedLookup) -> Result<Self, DecodeError> {
+        Self {
+            local: lookup.local,
+            target: lookup.target,
+            config: lookup.config,
+            candidates: lookup.candidates,
+            contacted: lookup.contacted,
+            value: lookup.value,
+            in_flight: lookup.in_flight,
+            done: lookup.done,
+        }
+        .checked()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        codec::{Decode, DecodeError, Encode},
+        network::Address,
+        node::{ConnectionStep, Contact},
+        primitives::GUID,
+    };
+
+    use super::{Lookup, LookupConfig};
+
+    fn contact(i: u32) -> Contact {
+        Contact::new(GUID::from(i), Address::new(i))
+    }
+
+    fn seeking(step: Option<ConnectionStep>) -> GUID {
+        match step {
+            Some(ConnectionStep::Seeking { id }) => id,
+            _ => panic!("Expected lookup to be seeking"),
+        }
+    }
+
+    #[test]
+    fn respects_alpha() {
+        let config = LookupConfig { alpha: 2, k: 4 };
+        let seeds = (1..=4).map(contact);
+        let mut lookup = Lookup::new(GUID::MAX, GUID::MIN, config, seeds);
+
+        assert_eq!(seeking(lookup.poll()), GUID::from(1u32));
+        assert_eq!(seeking(lookup.poll()), GUID::from(2u32));
+        assert!(lookup.poll().is_none());
+
+        lookup.on_response(&GUID::from(1u32), &[]);
+        assert_eq!(seeking(lookup.poll()), GUID::from(3u32));
+    }
+
+    #[test]
+    fn converges_on_closest() {
+        let config = LookupConfig { alpha: 1, k: 2 };
+        let mut lookup = Lookup::new(GUID::MAX, GUID::MIN, config, [contact(8), contact(9)]);
+
+        let id = seeking(lookup.poll());
+        assert_eq!(id, GUID::from(8u32));
+        lookup.on_response(&id, &[contact(1), contact(2)]);
+
+        let id = seeking(lookup.poll());
+        assert_eq!(id, GUID::from(1u32));
+        lookup.on_response(&id, &[]);
+
+        let id = seeking(lookup.poll());
+        assert_eq!(id, GUID::from(2u32));
+        lookup.on_response(&id, &[contact(8)]);
+
+        assert!(matches!(lookup.poll(), Some(ConnectionStep::Done { .. })));
+
+        let result = lookup.result();
+        assert_eq!(result.closest, [contact(1), contact(2)]);
+        assert_eq!(result.hops, 2);
+        assert_eq!(result.contacted, [contact(8), contact(1), contact(2)]);
+    }
+
+    #[test]
+    fn failures_are_skipped() {
+        let config = LookupConfig { alpha: 3, k: 2 };
+        let seeds = (1..=3).map(contact);
+        let mut lookup = Lookup::new(GUID::MAX, GUID::MIN, config, seeds);
+
+        let id_a = seeking(lookup.poll());
+        let id_b = seeking(lookup.poll());
+        assert!(lookup.poll().is_none());
+
+        lookup.on_step(&ConnectionStep::Failed { id: id_a });
+        lookup.on_response(&id_b, &[]);
+
+        let id_c = seeking(lookup.poll());
+        assert_eq!(id_c, GUID::from(3u32));
+        lookup.on_response(&id_c, &[]);
+
+        assert!(matches!(lookup.poll(), Some(ConnectionStep::Done { .. })));
+
+        let result = lookup.result();
+        assert_eq!(result.closest, [contact(2), contact(3)]);
+        assert_eq!(result.failed, [contact(1)]);
+    }
+
+    #[test]
+    fn value_ends_lookup() {
+        let config = LookupConfig { alpha: 2, k: 4 };
+        let seeds = (1..=4).map(contact);
+        let mut lookup = Lookup::new(GUID::MAX, GUID::MIN, config, seeds);
+
+        let id_a = seeking(lookup.poll());
+        let id_b = seeking(lookup.poll());
+
+        lookup.on_response(&id_a, &[]);
+        lookup.on_value(&id_b, vec![42]);
+
+        assert_eq!(lookup.poll(), Some(ConnectionStep::Done { data: vec![42] }));
+
+        let result = lookup.result();
+        assert_eq!(result.value, Some(vec![42]));
+        assert_eq!(result.closest, [contact(1)]);
+    }
+
+    #[test]
+    fn ignores_local_and_unsolicited() {
+        let config = LookupConfig { alpha: 1, k: 1 };
+        let mut lookup = Lookup::new(GUID::from(1u32), GUID::MIN, config, [contact(1)]);
+
+        assert!(matches!(lookup.poll(), Some(ConnectionStep::Done { .. })));
+
+        lookup.on_response(&GUID::from(5u32), &[contact(2)]);
+        assert!(lookup.contact(&GUID::from(2u32)).is_none());
+    }
+
+    #[test]
+    fn late_response_ignored() {
+        let config = LookupConfig { alpha: 3, k: 2 };
+        let seeds = [contact(4), contact(5)];
+        let mut lookup = Lookup::new(GUID::MAX, GUID::MIN, config, seeds);
+
+        seeking(lookup.poll());
+        seeking(lookup.poll());
+        lookup.on_response(&GUID::from(4u32), &[contact(1), contact(2)]);
+        seeking(lookup.poll());
+        seeking(lookup.poll());
+        lookup.on_response(&GUID::from(1u32), &[]);
+        lookup.on_response(&GUID::from(2u32), &[]);
+
+        // Done while 5, outside the top k, is still pending
+        assert!(matches!(lookup.poll(), Some(ConnectionStep::Done { .. })));
+        let result = lookup.result();
+
+        let mut failing = lookup.clone();
+        assert!(!failing.on_failure(&GUID::from(5u32)));
+        assert!(failing.result().failed.is_empty());
+
+        lookup.on_response(&GUID::from(5u32), &[contact(3)]);
+        assert!(lookup.contact(&GUID::from(3u32)).is_none());
+        assert_eq!(lookup.result().closest, result.closest);
+        assert_eq!(lookup.result().hops, result.hops);
+        assert!(matches!(lookup.poll(), Some(ConnectionStep::Done { .. })));
+    }
+
+    #[test]
+    fn decode_checks_in_flight() {
+        let config = LookupConfig { alpha: 2, k: 4 };
+        let seeds = (1..=4).map(contact);
+        let mut lookup = Lookup::new(GUID::MAX, GUID::MIN, config, seeds);
+        seeking(lookup.poll());
+        seeking(lookup.poll());
+
+        let bytes = lookup.to_bytes();
+        assert_eq!(Lookup::from_bytes(&bytes).unwrap().to_bytes(), bytes);
+
+        // In flight count, followed by the done flag
+        let mut invalid = bytes.clone();
+        invalid[bytes.len() - 2] = 0;
+        assert_eq!(
+            Lookup::from_bytes(&invalid).unwrap_err(),
+            DecodeError::ValueInvalid { kind: "lookup" }
+        );
+    }
+}
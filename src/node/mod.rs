@@ -0,0 +1,339 @@
+mod contact;
+mod kbucket;
+mod lookup;
+mod protocol;
+mod routing;
+mod storage;
+mod wire;
+
+use blake2::Digest;
+use indexmap::IndexMap;
+use rand::Rng;
+
+use crate::{
+    codec::{Decode, DecodeError, Decoder, Encode},
+    network::{Address, Network},
+    primitives::{GuidHasher, GUID, GUID_BYTES},
+    simulator::{SimRng, Time},
+};
+
+pub use contact::Contact;
+pub use kbucket::{BucketUpdate, KBucket};
+pub use lookup::{Lookup, LookupConfig, LookupResult};
+pub use protocol::{
+    Action, Message, NodeEvent, Operation, OperationResult, ProtocolConfig, Rpc, Timer,
+};
+pub use routing::RoutingTable;
+pub use storage::StoredValue;
+pub use wire::{WireError, HEADER_LEN, MAX_FRAME_LEN, MAX_VALUE_LEN};
+
+/// Maximum number of contacts per bucket.
+pub const K: usize = 20;
+
+pub type DATA = u8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConnectionStep {
+    Done { data: Vec<DATA> },
+    Failed { id: GUID },
+    Seeking { id: GUID },
+}
+
+/// Response to a FIND_VALUE request, see [`Node::query`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum QueryResponse {
+    /// The node holds the value.
+    Value(Vec<DATA>),
+    /// The node does not hold the value, and points to closer contacts.
+    Contacts(Vec<Contact>),
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Node {
+    guid: GUID,
+    address: Address,
+    peers: RoutingTable<K>,
+    storage: IndexMap<GUID, StoredValue>,
+    /// Values this node originally published, which it republishes.
+    published: IndexMap<GUID, Vec<DATA>>,
+    operations: IndexMap<u64, protocol::PendingOperation>,
+    next_operation: u64,
+    /// Requests waiting on a response, by RPC id.
+    requests: IndexMap<GUID, protocol::PendingRequest>,
+}
+
+impl Node {
+    /// Draws the GUID salt from `rng`, so that the GUID can be reproduced
+    /// from the simulation seed.
+    pub fn new(name: &str, address: Address, rng: &mut SimRng) -> Self {
+        let guid = Self::generate_guid(name, rng);
+        Self::with_guid(guid, address)
+    }
+
+    pub fn new_with_peers(
+        name: &str,
+        address: Address,
+        peers: &[Contact],
+        rng: &mut SimRng,
+    ) -> Self {
+        Self::new(name, address, rng).with_peers(peers)
+    }
+
+    /// Adds `peers` to the routing table of a freshly created node.
+    pub fn with_peers(mut self, peers: &[Contact]) -> Self {
+        for peer in peers {
+            // Every peer we are handed is alive, so a full bucket keeps its
+            // head and the newcomer goes to the replacement cache.
+            self.peers.update(*peer, |_| true);
+        }
+
+        self
+    }
+
+    pub(crate) fn with_guid(guid: GUID, address: Address) -> Self {
+        Self {
+            guid,
+            address,
+            peers: RoutingTable::new(guid),
+            storage: IndexMap::default(),
+            published: IndexMap::default(),
+            operations: IndexMap::new(),
+            next_operation: 0,
+            requests: IndexMap::new(),
+        }
+    }
+
+    fn generate_guid(name: &str, rng: &mut impl Rng) -> GUID {
+        let salt: [u8; GUID_BYTES] = rng.gen();
+
+        let mut hasher = GuidHasher::new();
+        hasher.update(salt);
+        hasher.update(name.as_bytes());
+
+        GUID::from_blake2b(hasher)
+    }
+
+    pub fn guid(&self) -> GUID {
+        self.guid
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    pub fn contact(&self) -> Contact {
+        Contact::new(self.guid, self.address)
+    }
+
+    pub fn peers(&self) -> &RoutingTable<K> {
+        &self.peers
+    }
+
+    pub fn storage(&self) -> &IndexMap<GUID, StoredValue> {
+        &self.storage
+    }
+
+    /// Handles a FIND_VALUE request: the value stored under `key` if this
+    /// node holds it, otherwise the `K` closest contacts to `key`.
+    pub fn query(&self, key: &GUID) -> QueryResponse {
+        match self.storage.get(key) {
+            Some(value) => QueryResponse::Value(value.data.clone()),
+            None => QueryResponse::Contacts(self.find_node(key)),
+        }
+    }
+
+    /// Handles a STORE request received at `now`. Storing a value again
+    /// resets its expiry, see [`Node::ttl`].
+    pub fn store(&mut self, now: Time, key: GUID, data: Vec<DATA>, config: &ProtocolConfig) {
+        let ttl = self.ttl(&key, config);
+        self.storage.insert(key, StoredValue::new(data, now, ttl));
+    }
+
+    /// How long a value stored under `key` is kept for, which decreases
+    /// exponentially with the number of known nodes closer to `key`, down
+    /// to the refresh interval.
+    pub fn ttl(&self, key: &GUID, config: &ProtocolConfig) -> Time {
+        let distance = self.guid.distance(key);
+        let between = self
+            .peers
+            .contacts()
+            .filter(|contact| contact.guid.distance(key) < distance)
+            .count();
+
+        storage::ttl(
+            config.value_ttl,
+            config.refresh_interval,
+            between,
+            config.lookup.k,
+        )
+    }
+
+    /// Drops the values which have expired by `now`, returning how many.
+    pub fn expire(&mut self, now: Time) -> usize {
+        let len = self.storage.len();
+        self.storage.retain(|_, value| !value.is_expired(now));
+        len - self.storage.len()
+    }
+
+    /// When the next stored value expires, if there are any.
+    pub fn next_expiry(&self) -> Option<Time> {
+        self.storage.values().map(StoredValue::expires).min()
+    }
+
+    /// Handles a FIND_NODE request: the `K` contacts closest to `target`
+    /// this node knows of.
+    pub fn find_node(&self, target: &GUID) -> Vec<Contact> {
+        self.peers.closest(target, K)
+    }
+
+    /// Starts an iterative lookup for `target`, seeded from the routing
+    /// table.
+    pub fn lookup(&self, target: GUID, config: LookupConfig) -> Lookup {
+        let seeds = self.peers.closest(&target, config.k);
+        Lookup::new(self.guid, target, config, seeds)
+    }
+
+    /// Records `contact` in the routing table, asking `network` whether the
+    /// head of its bucket is alive if it is full. This is meant for wiring a
+    /// network up before a simulation starts: while it runs, heads are
+    /// pinged with actual [`Message::Ping`] requests instead.
+    pub fn add_peer(&mut self, network: &Network, contact: Contact) -> Option<BucketUpdate> {
+        self.peers.update(contact, |head| network.ping(head))
+    }
+
+    /// Drops an unresponsive peer from the routing table.
+    pub fn remove_peer(&mut self, guid: &GUID) -> bool {
+        self.peers.remove(guid)
+    }
+}
+
+impl Encode for Node {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.guid.encode(out);
+        self.address.encode(out);
+        self.peers.encode(out);
+        self.storage.encode(out);
+        self.published.encode(out);
+        self.operations.encode(out);
+        self.next_operation.encode(out);
+        self.requests.encode(out);
+    }
+}
+
+impl Decode for Node {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            guid: GUID::decode(input)?,
+            address: Address::decode(input)?,
+            peers: RoutingTable::decode(input)?,
+            storage: IndexMap::decode(input)?,
+            published: IndexMap::decode(input)?,
+            operations: IndexMap::decode(input)?,
+            next_operation: u64::decode(input)?,
+            requests: IndexMap::decode(input)?,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+pub mod test {
+    use crate::{
+        codec::Encode,
+        network::Network,
+        node::{ConnectionStep, Operation, ProtocolConfig},
+        primitives::GUID,
+        simulator::Time,
+    };
+
+    use super::{Node, K};
+
+    #[test]
+    fn serde_round_trip() {
+        let mut network = Network::new(0);
+        let a = network.spawn("a");
+        let b = network.spawn_with_peers("b", &[a]);
+        let config = ProtocolConfig::default();
+
+        let mut node = network.remove(b.address).unwrap();
+        node.store(Time::from_secs(1), GUID::MAX, vec![1, 2], &config);
+        let target = GUID::from(7u8);
+        node.start(
+            Time::ZERO,
+            Operation::FindNode { target },
+            &config,
+            network.rng(),
+        );
+
+        let json = serde_json::to_string(&node).unwrap();
+        let decoded = serde_json::from_str::<Node>(&json).unwrap();
+        assert_eq!(decoded.to_bytes(), node.to_bytes());
+
+        let step = ConnectionStep::Seeking { id: target };
+        let json = serde_json::to_string(&step).unwrap();
+        assert_eq!(
+            json,
+            format!(r#"{{"Seeking":{{"id":"{}07"}}}}"#, "0".repeat(38))
+        );
+        assert_eq!(serde_json::from_str::<ConnectionStep>(&json).unwrap(), step);
+    }
+
+    #[test]
+    fn serde_checks_node() {
+        let mut network = Network::new(0);
+        let a = network.spawn("a");
+        let b = network.spawn_with_peers("b", &[a]);
+        let config = ProtocolConfig::default();
+
+        let mut node = network.remove(b.address).unwrap();
+        node.start(
+            Time::ZERO,
+            Operation::FindNode {
+                target: GUID::from(7u8),
+            },
+            &config,
+            network.rng(),
+        );
+        let json = serde_json::to_value(&node).unwrap();
+        let error = |json| {
+            serde_json::from_value::<Node>(json)
+                .err()
+                .map(|error| error.to_string())
+        };
+        assert_eq!(error(json.clone()), None);
+
+        // Missing bucket
+        let mut malformed = json.clone();
+        let buckets = malformed.pointer_mut("/peers/buckets").unwrap();
+        buckets.as_array_mut().unwrap().pop();
+        assert_eq!(error(malformed).as_deref(), Some("Invalid routing table"));
+
+        // Missing touch time
+        let mut malformed = json.clone();
+        let touched = malformed.pointer_mut("/peers/touched").unwrap();
+        touched.as_array_mut().unwrap().pop();
+        assert_eq!(error(malformed).as_deref(), Some("Invalid routing table"));
+
+        // Overfull bucket
+        let mut malformed = json.clone();
+        let bucket = malformed
+            .pointer_mut("/peers/buckets/0/contacts")
+            .unwrap()
+            .as_array_mut()
+            .unwrap();
+        let contact = serde_json::to_value(a).unwrap();
+        bucket.extend(std::iter::repeat_n(contact, K + 1));
+        assert_eq!(error(malformed).as_deref(), Some("Invalid k-bucket"));
+
+        // Query in flight without a pending candidate
+        let mut malformed = json;
+        let lookup = malformed
+            .get_mut("operations")
+            .and_then(|operations| operations.as_object_mut()?.values_mut().next())
+            .and_then(|operation| operation.pointer_mut("/lookups/0"))
+            .unwrap();
+        lookup["in_flight"] = (lookup["in_flight"].as_u64().unwrap() + 1).into();
+        assert_eq!(error(malformed).as_deref(), Some("Invalid lookup"));
+    }
+}
@@ -0,0 +1,1302 @@
+use rand::Rng;
+
+use crate::{
+    codec::{write_tag, Decode, DecodeError, Decoder, Encode},
+    network::Address,
+    primitives::GUID,
+    simulator::{SimRng, Time},
+};
+
+use super::{
+    BucketUpdate, ConnectionStep, Contact, Lookup, LookupConfig, LookupResult, Node, QueryResponse,
+    DATA,
+};
+
+/// Messages exchanged between nodes.
+///
+/// Every request (`Ping`, `FindNode`, `FindValue`, `Store`) is answered by
+/// the matching response (`Pong`, `Nodes` or `Value`, `StoreAck`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    Ping,
+    Pong,
+    FindNode {
+        target: GUID,
+    },
+    Nodes {
+        target: GUID,
+        contacts: Vec<Contact>,
+    },
+    FindValue {
+        key: GUID,
+    },
+    Value {
+        key: GUID,
+        data: Vec<DATA>,
+    },
+    Store {
+        key: GUID,
+        data: Vec<DATA>,
+    },
+    StoreAck {
+        key: GUID,
+    },
+}
+
+impl Message {
+    pub fn is_request(&self) -> bool {
+        matches!(
+            self,
+            Message::Ping
+                | Message::FindNode { .. }
+                | Message::FindValue { .. }
+                | Message::Store { .. }
+        )
+    }
+
+    pub fn is_response(&self) -> bool {
+        !self.is_request()
+    }
+
+    /// Name of the kind of message, in snake case.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Message::Ping => "ping",
+            Message::Pong => "pong",
+            Message::FindNode { .. } => "find_node",
+            Message::Nodes { .. } => "nodes",
+            Message::FindValue { .. } => "find_value",
+            Message::Value { .. } => "value",
+            Message::Store { .. } => "store",
+            Message::StoreAck { .. } => "store_ack",
+        }
+    }
+}
+
+/// A [`Message`] as sent over the network.
+///
+/// Requests carry a random 160-bit `id` which the response echoes back, so
+/// the requester can match it against what it is waiting on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Rpc {
+    pub id: GUID,
+    pub sender: Contact,
+    pub message: Message,
+}
+
+/// Timers a node can set for itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Timer {
+    /// The request with RPC id `id` may have gone unanswered.
+    RpcTimeout { id: GUID },
+    /// Refreshes the buckets no lookup went through lately.
+    Refresh,
+    /// Republishes the values stored on behalf of other nodes.
+    Republish,
+    /// Republishes the values this node originally published.
+    RepublishOriginals,
+}
+
+/// High-level operations a node can be asked to carry out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Operation {
+    /// Finds the nodes closest to `target`.
+    FindNode { target: GUID },
+    /// Replicates `data` on the nodes closest to `key`.
+    Store { key: GUID, data: Vec<DATA> },
+    /// Retrieves the value stored under `key`.
+    Query { key: GUID },
+    /// Joins the network through the peers the node already knows of: looks
+    /// up the node's own GUID, then refreshes every bucket farther than its
+    /// closest neighbour.
+    Join,
+    /// Looks up `target` to refresh the bucket it belongs in.
+    Refresh { target: GUID },
+    /// Stores `data` again on the nodes closest to `key`, so that it
+    /// survives the nodes holding it leaving.
+    Republish { key: GUID, data: Vec<DATA> },
+}
+
+impl Operation {
+    /// GUID the operation looks up, or `None` for a join which looks up the
+    /// GUID of the node carrying it out.
+    pub fn target(&self) -> Option<GUID> {
+        match self {
+            Operation::FindNode { target } | Operation::Refresh { target } => Some(*target),
+            Operation::Store { key, .. }
+            | Operation::Query { key }
+            | Operation::Republish { key, .. } => Some(*key),
+            Operation::Join => None,
+        }
+    }
+
+    /// Name of the kind of operation, in snake case.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Operation::FindNode { .. } => "find_node",
+            Operation::Store { .. } => "store",
+            Operation::Query { .. } => "query",
+            Operation::Join => "join",
+            Operation::Refresh { .. } => "refresh",
+            Operation::Republish { .. } => "republish",
+        }
+    }
+
+    /// Whether the operation is carried out by nodes on their own to keep
+    /// the network healthy.
+    pub fn is_maintenance(&self) -> bool {
+        matches!(
+            self,
+            Operation::Refresh { .. } | Operation::Republish { .. }
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProtocolConfig {
+    pub lookup: LookupConfig,
+    /// How long a node waits for a response before considering a peer
+    /// unresponsive.
+    pub rpc_timeout: Time,
+    /// Buckets no lookup went through for this long are refreshed.
+    pub refresh_interval: Time,
+    /// How often values stored on behalf of other nodes are republished.
+    pub republish_interval: Time,
+    /// How often a node republishes the values it originally published.
+    pub original_republish_interval: Time,
+    /// How long the nodes closest to a key keep its value, see
+    /// [`Node::ttl`].
+    pub value_ttl: Time,
+    /// Whether nodes report what happens inside them as [`NodeEvent`]s.
+    pub trace: bool,
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        Self {
+            lookup: LookupConfig::default(),
+            rpc_timeout: Time::from_secs(2),
+            refresh_interval: Time::from_secs(60 * 60),
+            republish_interval: Time::from_secs(60 * 60),
+            original_republish_interval: Time::from_secs(24 * 60 * 60),
+            // Slightly longer than the original republish interval, so that
+            // values are stored again before they expire
+            value_ttl: Time::from_secs(24 * 60 * 60 + 10),
+            trace: false,
+        }
+    }
+}
+
+/// A completed [`Operation`].
+#[derive(Clone, Debug)]
+pub struct OperationResult {
+    /// Node which carried out the operation.
+    pub address: Address,
+    pub operation: Operation,
+    pub started: Time,
+    pub finished: Time,
+    /// Result of the lookup for the operation's target.
+    pub lookup: LookupResult,
+    /// Requests sent on behalf of the operation, across all its lookups.
+    pub messages: usize,
+}
+
+/// Something which happened inside a node, reported through
+/// [`Action::Trace`] when [`ProtocolConfig::trace`] is set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeEvent {
+    /// `contact` was added to the bucket at `bucket`.
+    BucketInsert { contact: Contact, bucket: usize },
+    /// `contact` did not respond and was removed from the bucket at
+    /// `bucket`.
+    BucketEvict { contact: Contact, bucket: usize },
+    /// A value of `bytes` bytes was stored under `key`, for `ttl`.
+    Store { key: GUID, bytes: usize, ttl: Time },
+    /// A lookup for `target` queried `peer`.
+    LookupQuery { target: GUID, peer: Contact },
+    /// `peer` answered a lookup for `target` with `contacts` contacts, or
+    /// with the value if `found`.
+    LookupResponse {
+        target: GUID,
+        peer: Contact,
+        contacts: usize,
+        found: bool,
+    },
+    /// `peer` failed to answer a lookup for `target`, as a
+    /// [`ConnectionStep::Failed`].
+    LookupFailure { target: GUID, peer: Contact },
+}
+
+/// Side effects requested by a node, which the simulator carries out.
+#[derive(Clone, Debug)]
+pub enum Action {
+    Send { to: Contact, rpc: Rpc },
+    SetTimer { delay: Time, timer: Timer },
+    Trace(NodeEvent),
+    Complete(OperationResult),
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(super) struct PendingOperation {
+    operation: Operation,
+    /// Lookup for the operation's target, followed by the bucket refreshes
+    /// of a join.
+    lookups: Vec<Lookup>,
+    started: Time,
+    messages: usize,
+}
+
+/// Why a request was sent, which decides what to do with its response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum Purpose {
+    /// Query sent on behalf of lookup `lookup` of a pending operation.
+    Lookup { operation: u64, lookup: usize },
+    /// Liveness check of the head of a full bucket.
+    Ping,
+    /// Replication of a value.
+    Store,
+}
+
+/// A request waiting on its response.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(super) struct PendingRequest {
+    to: Contact,
+    purpose: Purpose,
+}
+
+impl Node {
+    /// Starts `operation`, returning the messages to send for it.
+    pub fn start(
+        &mut self,
+        now: Time,
+        operation: Operation,
+        config: &ProtocolConfig,
+        rng: &mut SimRng,
+    ) -> Vec<Action> {
+        let mut actions = Vec::new();
+
+        if let Operation::Query { key } = &operation {
+            if let Some(value) = self.storage.get(key) {
+                actions.push(Action::Complete(OperationResult {
+                    address: self.address,
+                    lookup: LookupResult {
+                        target: *key,
+                        value: Some(value.data.clone()),
+                        ..Default::default()
+                    },
+                    operation,
+                    started: now,
+                    finished: now,
+                    messages: 0,
+                }));
+                return actions;
+            }
+        }
+
+        if let Operation::Store { key, data } = &operation {
+            self.published.insert(*key, data.clone());
+        }
+
+        let target = operation.target().unwrap_or(self.guid);
+        let lookup = self.lookup(target, config.lookup);
+        self.peers.touch(&target, now);
+        let id = self.next_operation;
+        self.next_operation += 1;
+        self.operations.insert(
+            id,
+            PendingOperation {
+                operation,
+                lookups: vec![lookup],
+                started: now,
+                messages: 0,
+            },
+        );
+        self.advance(now, config, rng, &mut actions);
+
+        actions
+    }
+
+    /// Sends a PING to `contact`. If it goes unanswered, `contact` is dropped
+    /// from the routing table.
+    pub fn ping(
+        &mut self,
+        contact: Contact,
+        config: &ProtocolConfig,
+        rng: &mut SimRng,
+    ) -> Vec<Action> {
+        let mut actions = Vec::new();
+        self.request(
+            contact,
+            Message::Ping,
+            Purpose::Ping,
+            config,
+            rng,
+            &mut actions,
+        );
+        actions
+    }
+
+    /// Sets the timers which drive periodic maintenance: bucket refresh and
+    /// value republishing. Each timer sets itself again when it fires.
+    pub fn start_maintenance(&self, config: &ProtocolConfig) -> Vec<Action> {
+        vec![
+            Action::SetTimer {
+                delay: config.refresh_interval,
+                timer: Timer::Refresh,
+            },
+            Action::SetTimer {
+                delay: config.republish_interval,
+                timer: Timer::Republish,
+            },
+            Action::SetTimer {
+                delay: config.original_republish_interval,
+                timer: Timer::RepublishOriginals,
+            },
+        ]
+    }
+
+    /// Hands every stored value off to the `k` closest peers to its key it
+    /// knows of, before leaving the network gracefully.
+    pub fn leave(&mut self, config: &ProtocolConfig, rng: &mut SimRng) -> Vec<Action> {
+        let mut actions = Vec::new();
+        let keys = self.storage.keys().copied().collect::<Vec<_>>();
+
+        for key in keys {
+            for to in self.peers.closest(&key, config.lookup.k) {
+                let message = Message::Store {
+                    key,
+                    data: self.storage[&key].data.clone(),
+                };
+
+                self.request(to, message, Purpose::Store, config, rng, &mut actions);
+            }
+        }
+
+        actions
+    }
+
+    /// Handles an incoming RPC.
+    ///
+    /// Any message is proof that its sender is alive, so it is recorded in
+    /// the routing table first. Requests are answered right away, while
+    /// responses are matched against the pending-request table and dropped
+    /// if they do not answer anything this node is waiting on.
+    pub fn handle(
+        &mut self,
+        now: Time,
+        rpc: Rpc,
+        config: &ProtocolConfig,
+        rng: &mut SimRng,
+    ) -> Vec<Action> {
+        let Rpc {
+            id,
+            sender,
+            message,
+        } = rpc;
+        let mut actions = Vec::new();
+
+        self.observe(sender, config, rng, &mut actions);
+
+        let response = match message {
+            Message::Ping => Message::Pong,
+            Message::FindNode { target } => Message::Nodes {
+                target,
+                contacts: self.find_node(&target),
+            },
+            Message::FindValue { key } => match self.query(&key) {
+                QueryResponse::Value(data) => Message::Value { key, data },
+                QueryResponse::Contacts(contacts) => Message::Nodes {
+                    target: key,
+                    contacts,
+                },
+            },
+            Message::Store { key, data } => {
+                let bytes = data.len();
+                self.store(now, key, data, config);
+
+                let ttl = self.storage[&key].ttl;
+                trace(config, &mut actions, NodeEvent::Store { key, bytes, ttl });
+                Message::StoreAck { key }
+            }
+            response => {
+                self.on_response(now, id, sender, response, config, rng, &mut actions);
+                return actions;
+            }
+        };
+
+        actions.push(Action::Send {
+            to: sender,
+            rpc: Rpc {
+                id,
+                sender: self.contact(),
+                message: response,
+            },
+        });
+
+        actions
+    }
+
+    /// Handles one of this node's timers firing.
+    pub fn on_timer(
+        &mut self,
+        now: Time,
+        timer: Timer,
+        config: &ProtocolConfig,
+        rng: &mut SimRng,
+    ) -> Vec<Action> {
+        let mut actions = Vec::new();
+
+        match timer {
+            Timer::RpcTimeout { id } => {
+                // The request was answered in time
+                let Some(request) = self.requests.swap_remove(&id) else {
+                    return actions;
+                };
+
+                let peer = request.to;
+                match request.purpose {
+                    Purpose::Lookup { operation, lookup } => {
+                        let failed = self
+                            .operations
+                            .get_mut(&operation)
+                            .and_then(|pending| pending.lookups.get_mut(lookup))
+                            .and_then(|lookup| {
+                                lookup.on_failure(&peer.guid).then(|| lookup.target())
+                            });
+
+                        if let Some(target) = failed {
+                            let event = NodeEvent::LookupFailure { target, peer };
+                            trace(config, &mut actions, event);
+                            self.evict(peer, config, &mut actions);
+                            self.advance(now, config, rng, &mut actions);
+                        }
+                    }
+                    Purpose::Ping => {
+                        self.evict(peer, config, &mut actions);
+                    }
+                    Purpose::Store => {}
+                }
+            }
+            Timer::Refresh => {
+                let stale = (0..self.peers.depth())
+                    .filter(|i| self.peers.last_touched(*i) + config.refresh_interval <= now)
+                    .collect::<Vec<_>>();
+
+                for i in stale {
+                    let target = self.peers.random_id_in_bucket(i, rng);
+                    let operation = Operation::Refresh { target };
+                    actions.extend(self.start(now, operation, config, rng));
+                }
+
+                actions.push(Action::SetTimer {
+                    delay: config.refresh_interval,
+                    timer: Timer::Refresh,
+                });
+            }
+            Timer::Republish => {
+                let values = self
+                    .storage
+                    .iter()
+                    .filter(|(key, _)| !self.published.contains_key(*key))
+                    .map(|(key, value)| (*key, value.data.clone()))
+                    .collect::<Vec<_>>();
+
+                for (key, data) in values {
+                    let operation = Operation::Republish { key, data };
+                    actions.extend(self.start(now, operation, config, rng));
+                }
+
+                actions.push(Action::SetTimer {
+                    delay: config.republish_interval,
+                    timer: Timer::Republish,
+                });
+            }
+            Timer::RepublishOriginals => {
+                let values = self
+                    .published
+                    .iter()
+                    .map(|(key, data)| (*key, data.clone()))
+                    .collect::<Vec<_>>();
+
+                for (key, data) in values {
+                    let operation = Operation::Republish { key, data };
+                    actions.extend(self.start(now, operation, config, rng));
+                }
+
+                actions.push(Action::SetTimer {
+                    delay: config.original_republish_interval,
+                    timer: Timer::RepublishOriginals,
+                });
+            }
+        }
+
+        actions
+    }
+
+    /// Requests this node is still waiting on a response for.
+    pub fn pending_requests(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// Sends `message` to `to` under a fresh RPC id, and sets the timer after
+    /// which it is considered unanswered.
+    fn request(
+        &mut self,
+        to: Contact,
+        message: Message,
+        purpose: Purpose,
+        config: &ProtocolConfig,
+        rng: &mut SimRng,
+        actions: &mut Vec<Action>,
+    ) {
+        let id: GUID = rng.gen();
+
+        self.requests.insert(id, PendingRequest { to, purpose });
+        actions.push(Action::Send {
+            to,
+            rpc: Rpc {
+                id,
+                sender: self.contact(),
+                message,
+            },
+        });
+        actions.push(Action::SetTimer {
+            delay: config.rpc_timeout,
+            timer: Timer::RpcTimeout { id },
+        });
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn on_response(
+        &mut self,
+        now: Time,
+        id: GUID,
+        sender: Contact,
+        message: Message,
+        config: &ProtocolConfig,
+        rng: &mut SimRng,
+        actions: &mut Vec<Action>,
+    ) {
+        match self.requests.get(&id) {
+            Some(request) if request.to.guid == sender.guid => {}
+            _ => return,
+        }
+
+        let request = self
+            .requests
+            .swap_remove(&id)
+            .expect("Request was just found");
+
+        let Purpose::Lookup { operation, lookup } = request.purpose else {
+            // Pongs and store acks only matter as proof of liveness, which
+            // `observe` has already recorded
+            return;
+        };
+
+        if let Some(pending) = self.operations.get_mut(&operation) {
+            let is_query = matches!(pending.operation, Operation::Query { .. });
+            let Some(lookup) = pending.lookups.get_mut(lookup) else {
+                return;
+            };
+
+            let target = lookup.target();
+            let event = |contacts, found| NodeEvent::LookupResponse {
+                target,
+                peer: sender,
+                contacts,
+                found,
+            };
+
+            match message {
+                Message::Nodes { contacts, .. } => {
+                    trace(config, actions, event(contacts.len(), false));
+                    lookup.on_response(&sender.guid, &contacts);
+                }
+                Message::Value { data, .. } if is_query => {
+                    trace(config, actions, event(0, true));
+                    lookup.on_value(&sender.guid, data);
+                }
+                Message::Value { .. } => {
+                    trace(config, actions, event(0, false));
+                    lookup.on_response(&sender.guid, &[]);
+                }
+                _ => {
+                    let event = NodeEvent::LookupFailure {
+                        target,
+                        peer: sender,
+                    };
+                    trace(config, actions, event);
+                    lookup.on_failure(&sender.guid);
+                }
+            }
+        }
+
+        self.advance(now, config, rng, actions);
+    }
+
+    /// Records that `contact` was seen alive. If its bucket is full, the head
+    /// of the bucket is pinged and only evicted if it does not answer. If it
+    /// was not known yet, it is handed the values it should now hold.
+    fn observe(
+        &mut self,
+        contact: Contact,
+        config: &ProtocolConfig,
+        rng: &mut SimRng,
+        actions: &mut Vec<Action>,
+    ) {
+        match self.peers.insert(contact) {
+            Some(BucketUpdate::Inserted) => {
+                if let Some(bucket) = self.peers.bucket_index(&contact.guid) {
+                    trace(config, actions, NodeEvent::BucketInsert { contact, bucket });
+                }
+                self.hand_off(contact, config, rng, actions);
+            }
+            Some(BucketUpdate::Full { head }) => {
+                let pinging = self.requests.values().any(|request| {
+                    request.purpose == Purpose::Ping && request.to.guid == head.guid
+                });
+
+                if !pinging {
+                    self.request(head, Message::Ping, Purpose::Ping, config, rng, actions);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Drops `contact` from the routing table for not responding, which
+    /// promotes the most recently seen contact of the replacement cache.
+    fn evict(&mut self, contact: Contact, config: &ProtocolConfig, actions: &mut Vec<Action>) {
+        let Some(bucket) = self.peers.bucket_index(&contact.guid) else {
+            return;
+        };
+        let replacement = self
+            .peers
+            .bucket(bucket)
+            .and_then(|b| b.replacements().last().copied());
+
+        if !self.remove_peer(&contact.guid) {
+            return;
+        }
+
+        trace(config, actions, NodeEvent::BucketEvict { contact, bucket });
+        if let Some(contact) = replacement.filter(|r| self.peers.contains(&r.guid)) {
+            trace(config, actions, NodeEvent::BucketInsert { contact, bucket });
+        }
+    }
+
+    /// Stores on a newly met `contact` every value it is now one of the `k`
+    /// closest nodes to. Only the node closest to a key does so, so that a
+    /// joining node receives each value once rather than from every replica.
+    fn hand_off(
+        &mut self,
+        contact: Contact,
+        config: &ProtocolConfig,
+        rng: &mut SimRng,
+        actions: &mut Vec<Action>,
+    ) {
+        let keys = self.storage.keys().copied().collect::<Vec<_>>();
+
+        for key in keys {
+            let closest = self.peers.closest(&key, config.lookup.k);
+            let distance = self.guid.distance(&key);
+
+            let is_replica = closest.iter().any(|c| c.guid == contact.guid);
+            let is_closest = closest
+                .iter()
+                .filter(|c| c.guid != contact.guid)
+                .all(|c| c.guid.distance(&key) > distance);
+
+            if is_replica && is_closest {
+                let message = Message::Store {
+                    key,
+                    data: self.storage[&key].data.clone(),
+                };
+                self.request(contact, message, Purpose::Store, config, rng, actions);
+            }
+        }
+    }
+
+    /// Polls every pending lookup, sending out queries and finishing the
+    /// operations whose lookup is done.
+    fn advance(
+        &mut self,
+        now: Time,
+        config: &ProtocolConfig,
+        rng: &mut SimRng,
+        actions: &mut Vec<Action>,
+    ) {
+        let operations = self.operations.keys().copied().collect::<Vec<_>>();
+
+        for operation in operations {
+            loop {
+                let pending = &mut self.operations[&operation];
+                let mut seeking = None;
+                let mut done = true;
+
+                for (i, lookup) in pending.lookups.iter_mut().enumerate() {
+                    match lookup.poll() {
+                        Some(ConnectionStep::Seeking { id }) => {
+                            let to = *lookup.contact(&id).expect("Seeking unknown contact");
+                            seeking = Some((i, to, lookup.target()));
+                            break;
+                        }
+                        Some(ConnectionStep::Done { .. }) => {}
+                        Some(ConnectionStep::Failed { .. }) | None => done = false,
+                    }
+                }
+
+                if let Some((lookup, to, target)) = seeking {
+                    let message = match pending.operation {
+                        Operation::Query { key } => Message::FindValue { key },
+                        _ => Message::FindNode { target },
+                    };
+                    let purpose = Purpose::Lookup { operation, lookup };
+
+                    pending.messages += 1;
+                    trace(config, actions, NodeEvent::LookupQuery { target, peer: to });
+                    self.request(to, message, purpose, config, rng, actions);
+                    continue;
+                }
+
+                if !done {
+                    break;
+                }
+
+                // Bucket refreshes only start once the self-lookup has
+                // found the closest neighbours
+                if pending.operation == Operation::Join && pending.lookups.len() == 1 {
+                    let refreshes = self.refreshes(now, config, rng);
+
+                    if !refreshes.is_empty() {
+                        self.operations[&operation].lookups.extend(refreshes);
+                        continue;
+                    }
+                }
+
+                let pending = self
+                    .operations
+                    .shift_remove(&operation)
+                    .expect("Operation was just polled");
+                self.finish(now, pending, config, rng, actions);
+                break;
+            }
+        }
+    }
+
+    /// Lookups for a random GUID in every bucket farther than the closest
+    /// neighbour.
+    fn refreshes(&mut self, now: Time, config: &ProtocolConfig, rng: &mut SimRng) -> Vec<Lookup> {
+        let Some(closest) = self.peers.closest(&self.guid, 1).pop() else {
+            return Vec::new();
+        };
+        let Some(index) = self.peers.bucket_index(&closest.guid) else {
+            return Vec::new();
+        };
+
+        (0..index)
+            .map(|i| {
+                let target = self.peers.random_id_in_bucket(i, rng);
+                self.peers.touch(&target, now);
+                self.lookup(target, config.lookup)
+            })
+            .collect()
+    }
+
+    fn finish(
+        &mut self,
+        now: Time,
+        pending: PendingOperation,
+        config: &ProtocolConfig,
+        rng: &mut SimRng,
+        actions: &mut Vec<Action>,
+    ) {
+        let lookup = pending.lookups[0].result();
+        let mut messages = pending.messages;
+
+        match &pending.operation {
+            Operation::FindNode { .. } | Operation::Join | Operation::Refresh { .. } => {}
+            Operation::Store { key, data } | Operation::Republish { key, data } => {
+                for to in lookup.closest.iter() {
+                    let message = Message::Store {
+                        key: *key,
+                        data: data.clone(),
+                    };
+                    messages += 1;
+                    self.request(*to, message, Purpose::Store, config, rng, actions);
+                }
+            }
+            Operation::Query { key } => {
+                // Caches the value at the closest node which did not have it
+                if let (Some(data), Some(to)) = (&lookup.value, lookup.closest.first()) {
+                    let message = Message::Store {
+                        key: *key,
+                        data: data.clone(),
+                    };
+                    messages += 1;
+                    self.request(*to, message, Purpose::Store, config, rng, actions);
+                }
+            }
+        }
+
+        actions.push(Action::Complete(OperationResult {
+            address: self.address,
+            operation: pending.operation,
+            started: pending.started,
+            finished: now,
+            lookup,
+            messages,
+        }));
+    }
+}
+
+/// Reports `event` if nodes are traced.
+fn trace(config: &ProtocolConfig, actions: &mut Vec<Action>, event: NodeEvent) {
+    if config.trace {
+        actions.push(Action::Trace(event));
+    }
+}
+
+impl Encode for Message {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Message::Ping => write_tag(out, 0),
+            Message::Pong => write_tag(out, 1),
+            Message::FindNode { target } => {
+                write_tag(out, 2);
+                target.encode(out);
+            }
+            Message::Nodes { target, contacts } => {
+                write_tag(out, 3);
+                target.encode(out);
+                contacts.encode(out);
+            }
+            Message::FindValue { key } => {
+                write_tag(out, 4);
+                key.encode(out);
+            }
+            Message::Value { key, data } => {
+                write_tag(out, 5);
+                key.encode(out);
+                data.encode(out);
+            }
+            Message::Store { key, data } => {
+                write_tag(out, 6);
+                key.encode(out);
+                data.encode(out);
+            }
+            Message::StoreAck { key } => {
+                write_tag(out, 7);
+                key.encode(out);
+            }
+        }
+    }
+}
+
+impl Decode for Message {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(match input.read_u8()? {
+            0 => Message::Ping,
+            1 => Message::Pong,
+            2 => Message::FindNode {
+                target: GUID::decode(input)?,
+            },
+            3 => Message::Nodes {
+                target: GUID::decode(input)?,
+                contacts: Vec::decode(input)?,
+            },
+            4 => Message::FindValue {
+                key: GUID::decode(input)?,
+            },
+            5 => Message::Value {
+                key: GUID::decode(input)?,
+                data: Vec::decode(input)?,
+            },
+            6 => Message::Store {
+                key: GUID::decode(input)?,
+                data: Vec::decode(input)?,
+            },
+            7 => Message::StoreAck {
+                key: GUID::decode(input)?,
+            },
+            tag => {
+                return Err(DecodeError::TagInvalid {
+                    kind: "message",
+                    tag,
+                })
+            }
+        })
+    }
+}
+
+impl Encode for Rpc {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.id.encode(out);
+        self.sender.encode(out);
+        self.message.encode(out);
+    }
+}
+
+impl Decode for Rpc {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            id: GUID::decode(input)?,
+            sender: Contact::decode(input)?,
+            message: Message::decode(input)?,
+        })
+    }
+}
+
+impl Encode for Timer {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Timer::RpcTimeout { id } => {
+                write_tag(out, 0);
+                id.encode(out);
+            }
+            Timer::Refresh => write_tag(out, 1),
+            Timer::Republish => write_tag(out, 2),
+            Timer::RepublishOriginals => write_tag(out, 3),
+        }
+    }
+}
+
+impl Decode for Timer {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(match input.read_u8()? {
+            0 => Timer::RpcTimeout {
+                id: GUID::decode(input)?,
+            },
+            1 => Timer::Refresh,
+            2 => Timer::Republish,
+            3 => Timer::RepublishOriginals,
+            tag => return Err(DecodeError::TagInvalid { kind: "timer", tag }),
+        })
+    }
+}
+
+impl Encode for Operation {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Operation::FindNode { target } => {
+                write_tag(out, 0);
+                target.encode(out);
+            }
+            Operation::Store { key, data } => {
+                write_tag(out, 1);
+                key.encode(out);
+                data.encode(out);
+            }
+            Operation::Query { key } => {
+                write_tag(out, 2);
+                key.encode(out);
+            }
+            Operation::Join => write_tag(out, 3),
+            Operation::Refresh { target } => {
+                write_tag(out, 4);
+                target.encode(out);
+            }
+            Operation::Republish { key, data } => {
+                write_tag(out, 5);
+                key.encode(out);
+                data.encode(out);
+            }
+        }
+    }
+}
+
+impl Decode for Operation {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(match input.read_u8()? {
+            0 => Operation::FindNode {
+                target: GUID::decode(input)?,
+            },
+            1 => Operation::Store {
+                key: GUID::decode(input)?,
+                data: Vec::decode(input)?,
+            },
+            2 => Operation::Query {
+                key: GUID::decode(input)?,
+            },
+            3 => Operation::Join,
+            4 => Operation::Refresh {
+                target: GUID::decode(input)?,
+            },
+            5 => Operation::Republish {
+                key: GUID::decode(input)?,
+                data: Vec::decode(input)?,
+            },
+            tag => {
+                return Err(DecodeError::TagInvalid {
+                    kind: "operation",
+                    tag,
+                })
+            }
+        })
+    }
+}
+
+impl Encode for ProtocolConfig {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.lookup.encode(out);
+        self.rpc_timeout.encode(out);
+        self.refresh_interval.encode(out);
+        self.republish_interval.encode(out);
+        self.original_republish_interval.encode(out);
+        self.value_ttl.encode(out);
+        self.trace.encode(out);
+    }
+}
+
+impl Decode for ProtocolConfig {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            lookup: LookupConfig::decode(input)?,
+            rpc_timeout: Time::decode(input)?,
+            refresh_interval: Time::decode(input)?,
+            republish_interval: Time::decode(input)?,
+            original_republish_interval: Time::decode(input)?,
+            value_ttl: Time::decode(input)?,
+            trace: bool::decode(input)?,
+        })
+    }
+}
+
+impl Encode for PendingOperation {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.operation.encode(out);
+        self.lookups.encode(out);
+        self.started.encode(out);
+        self.messages.encode(out);
+    }
+}
+
+impl Decode for PendingOperation {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            operation: Operation::decode(input)?,
+            lookups: Vec::decode(input)?,
+            started: Time::decode(input)?,
+            messages: usize::decode(input)?,
+        })
+    }
+}
+
+impl Encode for PendingRequest {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.to.encode(out);
+        match self.purpose {
+            Purpose::Lookup { operation, lookup } => {
+                write_tag(out, 0);
+                operation.encode(out);
+                lookup.encode(out);
+            }
+            Purpose::Ping => write_tag(out, 1),
+            Purpose::Store => write_tag(out, 2),
+        }
+    }
+}
+
+impl Decode for PendingRequest {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        let to = Contact::decode(input)?;
+        let purpose = match input.read_u8()? {
+            0 => Purpose::Lookup {
+                operation: u64::decode(input)?,
+                lookup: usize::decode(input)?,
+            },
+            1 => Purpose::Ping,
+            2 => Purpose::Store,
+            tag => {
+                return Err(DecodeError::TagInvalid {
+                    kind: "request purpose",
+                    tag,
+                })
+            }
+        };
+
+        Ok(Self { to, purpose })
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        network::Address,
+        node::{Contact, Node, K},
+        primitives::{GUID, GUID_BITS},
+        simulator::{SimRng, Time},
+    };
+
+    use super::{Action, Message, Operation, ProtocolConfig, Rpc, Timer};
+
+    fn sent(actions: &[Action]) -> Vec<(Contact, Rpc)> {
+        actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::Send { to, rpc } => Some((*to, rpc.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// `n` contacts which all fall in bucket 0 of `node`.
+    fn far_contacts(node: &Node, n: u32) -> Vec<Contact> {
+        let far = node.guid() ^ (GUID::from(1u8) << (GUID_BITS - 1));
+        (0..n)
+            .map(|i| Contact::new(far ^ GUID::from(i), Address::new(i + 1)))
+            .collect()
+    }
+
+    #[test]
+    fn responses_matched_by_id() {
+        let config = ProtocolConfig::default();
+        let mut rng = SimRng::new(0);
+        let mut node = Node::new("node", Address::new(0), &mut rng);
+        let peer = far_contacts(&node, 1)[0];
+        node = node.with_peers(&[peer]);
+
+        let operation = Operation::FindNode { target: peer.guid };
+        let actions = node.start(Default::default(), operation, &config, &mut rng);
+        let (to, request) = sent(&actions).remove(0);
+        assert_eq!(to, peer);
+        assert_eq!(request.sender, node.contact());
+        assert!(request.message.is_request());
+        assert_eq!(node.pending_requests(), 1);
+
+        let response = |id, sender| Rpc {
+            id,
+            sender,
+            message: Message::Nodes {
+                target: peer.guid,
+                contacts: vec![],
+            },
+        };
+
+        // Unknown id, or known id from the wrong peer
+        let stranger = Contact::new(peer.guid ^ GUID::from(1u8), Address::new(9));
+        node.handle(
+            Default::default(),
+            response(!request.id, peer),
+            &config,
+            &mut rng,
+        );
+        node.handle(
+            Default::default(),
+            response(request.id, stranger),
+            &config,
+            &mut rng,
+        );
+        assert_eq!(node.pending_requests(), 1);
+
+        let actions = node.handle(
+            Default::default(),
+            response(request.id, peer),
+            &config,
+            &mut rng,
+        );
+        assert_eq!(node.pending_requests(), 0);
+        assert!(matches!(actions.last(), Some(Action::Complete(_))));
+    }
+
+    #[test]
+    fn requests_are_answered_with_same_id() {
+        let config = ProtocolConfig::default();
+        let mut rng = SimRng::new(0);
+        let mut node = Node::new("node", Address::new(0), &mut rng);
+        let peer = far_contacts(&node, 1)[0];
+
+        let rpc = Rpc {
+            id: GUID::from(7u8),
+            sender: peer,
+            message: Message::Ping,
+        };
+        let (to, response) =
+            sent(&node.handle(Default::default(), rpc, &config, &mut rng)).remove(0);
+
+        assert_eq!(to, peer);
+        assert_eq!(response.id, GUID::from(7u8));
+        assert_eq!(response.message, Message::Pong);
+        assert!(node.peers().contains(&peer.guid));
+        assert_eq!(node.pending_requests(), 0);
+    }
+
+    #[test]
+    fn refresh_skips_touched_buckets() {
+        let config = ProtocolConfig::default();
+        let mut rng = SimRng::new(0);
+        let mut node = Node::new("node", Address::new(0), &mut rng);
+        let near = node.guid() ^ (GUID::from(1u8) << (GUID_BITS - 2));
+        let peers = [
+            far_contacts(&node, 1)[0],
+            Contact::new(near, Address::new(9)),
+        ];
+        node = node.with_peers(&peers);
+
+        let half_hour = Time::from_secs(30 * 60);
+        let target = peers[0].guid;
+        node.start(half_hour, Operation::FindNode { target }, &config, &mut rng);
+
+        let actions = node.on_timer(config.refresh_interval, Timer::Refresh, &config, &mut rng);
+        let targets = sent(&actions)
+            .into_iter()
+            .map(|(_, rpc)| match rpc.message {
+                Message::FindNode { target } => target,
+                _ => panic!("Expected FIND_NODE"),
+            })
+            .collect::<Vec<_>>();
+
+        assert!(!targets.is_empty());
+        assert!(targets
+            .iter()
+            .all(|target| node.peers().bucket_index(target) == Some(1)));
+        assert!(matches!(
+            actions.last(),
+            Some(Action::SetTimer {
+                timer: Timer::Refresh,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn full_bucket_pings_head() {
+        let config = ProtocolConfig::default();
+        let mut rng = SimRng::new(0);
+        let mut node = Node::new("node", Address::new(0), &mut rng);
+        let contacts = far_contacts(&node, K as u32 + 2);
+        node = node.with_peers(&contacts[..K]);
+
+        let ping = |sender| Rpc {
+            id: GUID::MIN,
+            sender,
+            message: Message::Ping,
+        };
+
+        // Both newcomers are cached, but the head is only pinged once
+        let actions = node.handle(Default::default(), ping(contacts[K]), &config, &mut rng);
+        let (to, head_ping) = sent(&actions).remove(0);
+        assert_eq!(to, contacts[0]);
+        assert_eq!(head_ping.message, Message::Ping);
+
+        let actions = node.handle(Default::default(), ping(contacts[K + 1]), &config, &mut rng);
+        assert_eq!(sent(&actions).len(), 1);
+        assert_eq!(node.pending_requests(), 1);
+
+        // The head does not answer: the most recent newcomer takes its place
+        let timer = Timer::RpcTimeout { id: head_ping.id };
+        node.on_timer(config.rpc_timeout, timer.clone(), &config, &mut rng);
+        assert!(!node.peers().contains(&contacts[0].guid));
+        assert!(node.peers().contains(&contacts[K + 1].guid));
+        assert_eq!(node.pending_requests(), 0);
+
+        // Firing again is a no-op
+        assert!(node
+            .on_timer(config.rpc_timeout, timer, &config, &mut rng)
+            .is_empty());
+    }
+}
@@ -0,0 +1,343 @@
+use rand::Rng;
+
+use crate::{
+    codec::{Decode, DecodeError, Decoder, Encode},
+    primitives::{GUID, GUID_BITS},
+    simulator::Time,
+};
+
+use super::{BucketUpdate, Contact, KBucket};
+
+/// Kademlia routing table: one [`KBucket`] per bit of the ID space.
+///
+/// Contacts are stored in the bucket whose index is the length of the prefix
+/// they share with the local GUID, so bucket `i` holds contacts at a XOR
+/// distance in `[2^(159 - i), 2^(160 - i))`. Farther buckets cover larger
+/// portions of the ID space, which gives each node a detailed view of its
+/// own neighbourhood and a coarse one of the rest of the network.
+///
+/// All [`GUID_BITS`] buckets are allocated upfront. In a network of `n`
+/// nodes, buckets past `log2(n)` are almost always empty, which costs
+/// little as empty buckets hold no contact storage.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "UncheckedRoutingTable<K>"))]
+pub struct RoutingTable<const K: usize> {
+    local: GUID,
+    buckets: Vec<KBucket<K>>,
+    /// Last time a lookup was started in the range of each bucket.
+    touched: Vec<Time>,
+}
+
+impl<const K: usize> RoutingTable<K> {
+    pub fn new(local: GUID) -> Self {
+        Self {
+            local,
+            buckets: (0..GUID_BITS).map(|_| KBucket::new()).collect(),
+            touched: vec![Time::ZERO; GUID_BITS as usize],
+        }
+    }
+
+    pub fn local(&self) -> GUID {
+        self.local
+    }
+
+    /// Index of the bucket `guid` belongs in, or `None` if it is the local
+    /// GUID.
+    pub fn bucket_index(&self, guid: &GUID) -> Option<usize> {
+        let cpl = self.local.common_prefix_len(guid);
+        (cpl < GUID_BITS).then_some(cpl as usize)
+    }
+
+    /// Bucket at `index`, or `None` if `index` is not below [`GUID_BITS`].
+    pub fn bucket(&self, index: usize) -> Option<&KBucket<K>> {
+        self.buckets.get(index)
+    }
+
+    /// Every bucket, starting from the farthest one. The position of a
+    /// bucket in this iterator is its index.
+    pub fn buckets(&self) -> impl Iterator<Item = &KBucket<K>> {
+        self.buckets.iter()
+    }
+
+    /// Number of buckets up to the closest one holding a contact. Buckets
+    /// past it cover parts of the ID space no known node is in.
+    pub fn depth(&self) -> usize {
+        self.buckets
+            .iter()
+            .rposition(|bucket| !bucket.is_empty())
+            .map_or(0, |index| index + 1)
+    }
+
+    /// Records activity from `contact`, see [`KBucket::update`]. Returns
+    /// `None` when `contact` is the local node, which is never stored.
+    pub fn update(
+        &mut self,
+        contact: Contact,
+        ping: impl FnOnce(&Contact) -> bool,
+    ) -> Option<BucketUpdate> {
+        let index = self.bucket_index(&contact.guid)?;
+        Some(self.buckets[index].update(contact, ping))
+    }
+
+    /// Records activity from `contact`, see [`KBucket::insert`]. Returns
+    /// `None` when `contact` is the local node, which is never stored.
+    pub fn insert(&mut self, contact: Contact) -> Option<BucketUpdate> {
+        let index = self.bucket_index(&contact.guid)?;
+        Some(self.buckets[index].insert(contact))
+    }
+
+    /// Random GUID which belongs in the bucket at `index`, to look up when
+    /// refreshing it.
+    pub fn random_id_in_bucket(&self, index: usize, rng: &mut impl Rng) -> GUID {
+        let bit = GUID::from(1u8) << (GUID_BITS - 1 - index as u32);
+        let low = GUID::MAX >> (index as u32 + 1);
+
+        self.local ^ bit ^ (rng.gen::<GUID>() & low)
+    }
+
+    /// Records that a lookup for `target` was started at `now`, which counts
+    /// as a refresh of the bucket it belongs in.
+    pub fn touch(&mut self, target: &GUID, now: Time) {
+        if let Some(touched) = self
+            .bucket_index(target)
+            .and_then(|index| self.touched.get_mut(index))
+        {
+            *touched = now;
+        }
+    }
+
+    /// Last time a lookup was started in the range of the bucket at `index`,
+    /// or [`Time::ZERO`] if there never was.
+    pub fn last_touched(&self, index: usize) -> Time {
+        self.touched.get(index).copied().unwrap_or_default()
+    }
+
+    pub fn remove(&mut self, guid: &GUID) -> bool {
+        match self.bucket_index(guid) {
+            Some(index) => self.buckets[index].remove(guid),
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, guid: &GUID) -> bool {
+        self.get(guid).is_some()
+    }
+
+    pub fn get(&self, guid: &GUID) -> Option<&Contact> {
+        let index = self.bucket_index(guid)?;
+        self.buckets
+            .get(index)?
+            .contacts()
+            .find(|contact| contact.guid == *guid)
+    }
+
+    /// All contacts in the table, from the farthest bucket to the closest.
+    pub fn contacts(&self) -> impl Iterator<Item = &Contact> {
+        self.buckets.iter().flat_map(|bucket| bucket.contacts())
+    }
+
+    /// Up to `n` contacts closest to `target`, sorted by increasing XOR
+    /// distance.
+    pub fn closest(&self, target: &GUID, n: usize) -> Vec<Contact> {
+        let mut contacts = self.contacts().copied().collect::<Vec<_>>();
+
+        if contacts.len() > n && n > 0 {
+            contacts.select_nth_unstable_by_key(n - 1, |c| c.guid.distance(target));
+        }
+        contacts.truncate(n);
+        contacts.sort_unstable_by_key(|c| c.guid.distance(target));
+
+        contacts
+    }
+
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.iter().all(|bucket| bucket.is_empty())
+    }
+
+    /// Rejects tables without exactly one bucket and one touch time per bit
+    /// of the ID space, which bucket indexing relies on.
+    fn checked(self) -> Result<Self, DecodeError> {
+        if self.buckets.len() != GUID_BITS as usize || self.touched.len() != self.buckets.len() {
+            return Err(DecodeError::ValueInvalid {
+                kind: "routing table",
+            });
+        }
+
+        Ok(self)
+    }
+}
+
+impl<const K: usize> Encode for RoutingTable<K> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.local.encode(out);
+        self.buckets.encode(out);
+        self.touched.encode(out);
+    }
+}
+
+impl<const K: usize> Decode for RoutingTable<K> {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Self {
+            local: GUID::decode(input)?,
+            buckets: Vec::decode(input)?,
+            touched: Vec::decode(input)?,
+        }
+        .checked()
+    }
+}
+
+/// [`RoutingTable`] as deserialized, before it is checked.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct UncheckedRoutingTable<const K: usize> {
+    local: GUID,
+    buckets: Vec<KBucket<K>>,
+    touched: Vec<Time>,
+}
+
+#[cfg(feature = "serde")]
+impl<const K: usize> TryFrom<UncheckedRoutingTable<K>> for RoutingTable<K> {
+    type Error = DecodeError;
+
+    fn try_from(table: UncheckedRoutingTable<K>) -> Result<Self, DecodeError> {
+        Self {
+            local: table.local,
+            buckets: table.buckets,
+            touched: table.touched,
+        }
+        .checked()
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        codec::{Decode, DecodeError, Encode},
+        network::Address,
+        node::{Contact, KBucket},
+        primitives::{GUID, GUID_BITS},
+        simulator::{SimRng, Time},
+    };
+
+    use super::RoutingTable;
+
+    fn contact(i: u32) -> Contact {
+        Contact::new(GUID::from(i), Address::new(i))
+    }
+
+    #[test]
+    fn bucket_index() {
+        let table = RoutingTable::<4>::new(GUID::MIN);
+
+        assert_eq!(table.bucket_index(&GUID::MIN), None);
+        assert_eq!(table.bucket_index(&GUID::MAX), Some(0));
+        assert_eq!(table.bucket_index(&(GUID::MAX >> 1)), Some(1));
+        assert_eq!(table.bucket_index(&GUID::from(1u32)), Some(159));
+    }
+
+    #[test]
+    fn random_id_in_bucket() {
+        let table = RoutingTable::<4>::new(GUID::from(42u32));
+        let mut rng = SimRng::new(0);
+
+        for index in [0, 1, 80, 158, 159] {
+            let id = table.random_id_in_bucket(index, &mut rng);
+            assert_eq!(table.bucket_index(&id), Some(index));
+        }
+    }
+
+    #[test]
+    fn touch() {
+        let mut table = RoutingTable::<4>::new(GUID::MIN);
+        table.update(contact(8), |_| true);
+
+        table.touch(&GUID::from(9u32), Time::from_secs(5));
+        table.touch(&GUID::from(1u32), Time::from_secs(5));
+
+        assert_eq!(table.last_touched(156), Time::from_secs(5));
+        assert_eq!(table.last_touched(155), Time::ZERO);
+        assert_eq!(table.last_touched(159), Time::from_secs(5));
+        assert_eq!(table.depth(), 157);
+    }
+
+    #[test]
+    fn update_skips_local() {
+        let mut table = RoutingTable::<4>::new(GUID::from(42u32));
+
+        assert_eq!(table.update(contact(42), |_| unreachable!()), None);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn update_and_remove() {
+        let mut table = RoutingTable::<4>::new(GUID::MIN);
+
+        for i in 1..=8u32 {
+            table.update(contact(i), |_| true);
+        }
+
+        assert_eq!(table.len(), 8);
+        assert_eq!(table.bucket(158).map(|b| b.len()), Some(2));
+        assert_eq!(table.bucket(157).map(|b| b.len()), Some(4));
+        assert!(table.bucket(100).unwrap().is_empty());
+        assert_eq!(table.buckets().count(), GUID_BITS as usize);
+        assert_eq!(table.depth(), GUID_BITS as usize);
+        assert!(table.contains(&GUID::from(5u32)));
+        assert!(table.remove(&GUID::from(5u32)));
+        assert!(!table.contains(&GUID::from(5u32)));
+        assert_eq!(table.len(), 7);
+    }
+
+    #[test]
+    fn decode_checks_bucket_count() {
+        let mut table = RoutingTable::<4>::new(GUID::MIN);
+        table.update(contact(1), |_| true);
+        assert_eq!(
+            RoutingTable::<4>::from_bytes(&table.to_bytes())
+                .unwrap()
+                .len(),
+            1
+        );
+
+        table.buckets.push(KBucket::new());
+        assert_eq!(
+            RoutingTable::<4>::from_bytes(&table.to_bytes()).unwrap_err(),
+            DecodeError::ValueInvalid {
+                kind: "routing table"
+            }
+        );
+
+        table.buckets.pop();
+        table.touched.pop();
+        assert!(RoutingTable::<4>::from_bytes(&table.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn closest() {
+        let mut table = RoutingTable::<20>::new(GUID::MIN);
+
+        for i in 1..=32u32 {
+            table.update(contact(i), |_| true);
+        }
+
+        let target = GUID::from(0b10100u32);
+        let closest = table.closest(&target, 4);
+
+        assert_eq!(
+            closest,
+            [
+                contact(0b10100),
+                contact(0b10101),
+                contact(0b10110),
+                contact(0b10111)
+            ]
+        );
+        assert_eq!(table.closest(&target, 64).len(), 32);
+        assert!(table.closest(&target, 0).is_empty());
+    }
+}
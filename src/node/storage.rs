@@ -0,0 +1,104 @@
+use crate::{
+    codec::{Decode, DecodeError, Decoder, Encode},
+    simulator::Time,
+};
+
+use super::DATA;
+
+/// A value held by a node, see [`Node::storage`](super::Node::storage).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StoredValue {
+    pub data: Vec<DATA>,
+    /// When the value was last stored on this node.
+    pub published: Time,
+    /// How long after `published` the value expires.
+    pub ttl: Time,
+}
+
+impl StoredValue {
+    pub fn new(data: Vec<DATA>, published: Time, ttl: Time) -> Self {
+        Self {
+            data,
+            published,
+            ttl,
+        }
+    }
+
+    pub fn expires(&self) -> Time {
+        self.published + self.ttl
+    }
+
+    pub fn is_expired(&self, now: Time) -> bool {
+        self.expires() <= now
+    }
+}
+
+/// TTL of a value stored on a node with `between` known nodes closer to its
+/// key. Nodes among the `k` closest keep it for the full `ttl`, past those
+/// it halves for every extra node in between, so that values cached far from
+/// their key expire quickly. It never drops below `min` though, or the value
+/// would expire as soon as it is stored.
+pub fn ttl(ttl: Time, min: Time, between: usize, k: usize) -> Time {
+    let excess = between.saturating_sub(k) as u32;
+    let micros = ttl.as_micros().checked_shr(excess).unwrap_or_default();
+
+    Time::from_micros(micros).max(min.min(ttl))
+}
+
+impl Encode for StoredValue {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.data.encode(out);
+        self.published.encode(out);
+        self.ttl.encode(out);
+    }
+}
+
+impl Decode for StoredValue {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self::new(
+            Vec::decode(input)?,
+            Time::decode(input)?,
+            Time::decode(input)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::simulator::Time;
+
+    use super::{ttl, StoredValue};
+
+    #[test]
+    fn ttl_halves_past_k() {
+        let full = Time::from_secs(64);
+
+        assert_eq!(ttl(full, Time::ZERO, 0, 2), full);
+        assert_eq!(ttl(full, Time::ZERO, 2, 2), full);
+        assert_eq!(ttl(full, Time::ZERO, 3, 2), Time::from_secs(32));
+        assert_eq!(ttl(full, Time::ZERO, 8, 2), Time::from_secs(1));
+    }
+
+    #[test]
+    fn ttl_floor() {
+        let full = Time::from_secs(64);
+        let min = Time::from_secs(2);
+
+        assert_eq!(ttl(full, min, 3, 2), Time::from_secs(32));
+        assert_eq!(ttl(full, min, 8, 2), min);
+        // Past 64 halvings, the shift alone would leave nothing
+        assert_eq!(ttl(full, min, 2 + 64, 2), min);
+        assert_eq!(ttl(full, min, 2 + 100, 2), min);
+        assert_eq!(ttl(min, full, 100, 2), min);
+    }
+
+    #[test]
+    fn expiry() {
+        let value = StoredValue::new(vec![1], Time::from_secs(10), Time::from_secs(5));
+
+        assert_eq!(value.expires(), Time::from_secs(15));
+        assert!(!value.is_expired(Time::from_secs(14)));
+        assert!(value.is_expired(Time::from_secs(15)));
+    }
+}
@@ -0,0 +1,211 @@
+use crate::{
+    codec::{Decode, DecodeError, Encode},
+    primitives::GUID_BYTES,
+};
+
+use super::Rpc;
+
+/// Size of the length prefix of a frame.
+pub const HEADER_LEN: usize = 4;
+
+/// Largest RPC a frame may hold, which bounds what a receiver has to buffer.
+pub const MAX_FRAME_LEN: usize = 64 * 1024;
+
+/// Largest value a STORE or a FIND_VALUE response can carry. Besides the
+/// value, the RPC holds its id, the sender, the tag, the key and the length
+/// of the value: three GUIDs and at most nine bytes of varints and tag.
+pub const MAX_VALUE_LEN: usize = MAX_FRAME_LEN - 3 * GUID_BYTES - 9;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum WireError {
+    /// The frame is too short to hold its length prefix.
+    HeaderMissing,
+    /// The length prefix is larger than [`MAX_FRAME_LEN`].
+    FrameTooLarge { len: usize },
+    /// The length prefix does not match the length of the rest of the frame.
+    LengthMismatch { declared: usize, actual: usize },
+    /// The frame does not hold a valid RPC.
+    Payload(DecodeError),
+}
+
+impl std::fmt::Display for WireError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WireError::HeaderMissing => write!(f, "Frame shorter than its length prefix"),
+            WireError::FrameTooLarge { len } => {
+                write!(f, "Frame of {len} bytes, larger than {MAX_FRAME_LEN}")
+            }
+            WireError::LengthMismatch { declared, actual } => {
+                write!(f, "Frame declares {declared} bytes but holds {actual}")
+            }
+            WireError::Payload(err) => write!(f, "Malformed RPC: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for WireError {}
+
+impl From<DecodeError> for WireError {
+    fn from(err: DecodeError) -> Self {
+        WireError::Payload(err)
+    }
+}
+
+impl Rpc {
+    /// Encodes the RPC as a frame, which is how it travels over the network:
+    /// a big-endian `u32` holding the length of the rest of the frame,
+    /// followed by the RPC in the [`Encode`] encoding. The RPC id, and the
+    /// GUID of the sender and of every contact, take up
+    /// [`GUID_BYTES`] raw bytes.
+    ///
+    /// Fails with [`WireError::FrameTooLarge`] if the RPC does not fit in
+    /// [`MAX_FRAME_LEN`], which only happens for values larger than
+    /// [`MAX_VALUE_LEN`].
+    pub fn to_frame(&self) -> Result<Vec<u8>, WireError> {
+        let mut frame = vec![0; HEADER_LEN];
+        self.encode(&mut frame);
+
+        let len = frame.len() - HEADER_LEN;
+        if len > MAX_FRAME_LEN {
+            return Err(WireError::FrameTooLarge { len });
+        }
+        frame[..HEADER_LEN].copy_from_slice(&(len as u32).to_be_bytes());
+
+        Ok(frame)
+    }
+
+    /// Decodes a frame produced by [`Rpc::to_frame`].
+    pub fn from_frame(frame: &[u8]) -> Result<Self, WireError> {
+        let (header, payload) = frame
+            .split_first_chunk::<HEADER_LEN>()
+            .ok_or(WireError::HeaderMissing)?;
+        let declared = u32::from_be_bytes(*header) as usize;
+
+        if declared > MAX_FRAME_LEN {
+            return Err(WireError::FrameTooLarge { len: declared });
+        }
+        if declared != payload.len() {
+            return Err(WireError::LengthMismatch {
+                declared,
+                actual: payload.len(),
+            });
+        }
+
+        Ok(Rpc::from_bytes(payload)?)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        codec::DecodeError,
+        network::Address,
+        node::{Contact, Message, Rpc},
+        primitives::{GUID, GUID_BYTES},
+    };
+
+    use super::{WireError, HEADER_LEN, MAX_FRAME_LEN, MAX_VALUE_LEN};
+
+    fn rpc(message: Message) -> Rpc {
+        Rpc {
+            id: GUID::MAX,
+            sender: Contact::new(GUID::from(1u8), Address::new(300)),
+            message,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let contacts = (0..3u8)
+            .map(|i| Contact::new(GUID::from(i), Address::new(i.into())))
+            .collect();
+        let messages = [
+            Message::Ping,
+            Message::FindNode {
+                target: GUID::from(2u8),
+            },
+            Message::Nodes {
+                target: GUID::from(2u8),
+                contacts,
+            },
+            Message::Store {
+                key: GUID::MIN,
+                data: vec![1, 2, 3],
+            },
+        ];
+
+        for message in messages {
+            let rpc = rpc(message);
+            assert_eq!(Rpc::from_frame(&rpc.to_frame().unwrap()), Ok(rpc));
+        }
+    }
+
+    #[test]
+    fn layout() {
+        let frame = rpc(Message::Pong).to_frame().unwrap();
+
+        // Id, sender GUID, sender address as a two bytes varint, and tag
+        let len = 2 * GUID_BYTES + 2 + 1;
+        assert_eq!(frame.len(), HEADER_LEN + len);
+        assert_eq!(frame[..HEADER_LEN], (len as u32).to_be_bytes());
+        assert_eq!(
+            frame[HEADER_LEN..HEADER_LEN + GUID_BYTES],
+            [0xff; GUID_BYTES]
+        );
+        assert_eq!(frame[frame.len() - 1], 1);
+    }
+
+    #[test]
+    fn malformed() {
+        let frame = rpc(Message::Ping).to_frame().unwrap();
+
+        assert_eq!(Rpc::from_frame(&frame[..2]), Err(WireError::HeaderMissing));
+        assert_eq!(
+            Rpc::from_frame(&frame[..frame.len() - 1]),
+            Err(WireError::LengthMismatch {
+                declared: frame.len() - HEADER_LEN,
+                actual: frame.len() - HEADER_LEN - 1
+            })
+        );
+        assert_eq!(
+            Rpc::from_frame(&[0xff, 0xff, 0xff, 0xff]),
+            Err(WireError::FrameTooLarge {
+                len: u32::MAX as usize
+            })
+        );
+
+        let mut invalid = frame.clone();
+        *invalid.last_mut().unwrap() = 42;
+        assert_eq!(
+            Rpc::from_frame(&invalid),
+            Err(WireError::Payload(DecodeError::TagInvalid {
+                kind: "message",
+                tag: 42
+            }))
+        );
+    }
+
+    #[test]
+    fn value_too_large() {
+        let store = |len| {
+            rpc(Message::Store {
+                key: GUID::MAX,
+                data: vec![0; len],
+            })
+        };
+        // Largest sender address, whose varint takes the most bytes
+        let mut largest = store(MAX_VALUE_LEN);
+        largest.sender.address = Address::new(u32::MAX);
+
+        assert_eq!(
+            largest.to_frame().unwrap().len(),
+            HEADER_LEN + MAX_FRAME_LEN
+        );
+        assert_eq!(
+            store(MAX_FRAME_LEN).to_frame(),
+            Err(WireError::FrameTooLarge {
+                len: MAX_FRAME_LEN + 2 * GUID_BYTES + 2 + 1 + GUID_BYTES + 3
+            })
+        );
+    }
+}
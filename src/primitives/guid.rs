@@ -1,24 +1,60 @@
 use std::mem::size_of;
 
+use blake2::Digest;
+use rand::{
+    distributions::{Distribution, Standard},
+    Rng,
+};
+
 use crate::primitives::{add::add_carry, sub::sub_carry};
 
-use super::{t_word, GuidError, WORD_COUNT};
+use super::{
+    t_word, GuidError, GuidHasher, GUID_BITS, GUID_BYTES, PADDING_BITS, TOP_WORD_MASK, WORD_COUNT,
+};
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
 pub struct GUID {
     bytes: [t_word; WORD_COUNT],
 }
 
 impl GUID {
-    const MIN: GUID = GUID {
+    pub const MIN: GUID = GUID {
         bytes: [0; WORD_COUNT],
     };
 
-    const MAX: GUID = GUID {
-        // TODO: do the same for 32 bit
-        bytes: [4294967295, 18446744073709551615, 18446744073709551615],
+    pub const MAX: GUID = {
+        let mut bytes = [t_word::MAX; WORD_COUNT];
+        bytes[0] = TOP_WORD_MASK;
+        GUID { bytes }
     };
 
+    /// XOR distance between two GUIDs, as defined by Kademlia.
+    pub fn distance(&self, other: &Self) -> Self {
+        *self ^ *other
+    }
+
+    /// Number of leading zero bits, counted over the [`GUID_BITS`] of the GUID
+    /// rather than the underlying words.
+    pub fn leading_zeros(&self) -> u32 {
+        let mut zeros = 0;
+
+        for word in self.bytes {
+            if word == 0 {
+                zeros += t_word::BITS;
+            } else {
+                zeros += word.leading_zeros();
+                break;
+            }
+        }
+
+        zeros - PADDING_BITS
+    }
+
+    /// Number of high-order bits shared by two GUIDs.
+    pub fn common_prefix_len(&self, other: &Self) -> u32 {
+        self.distance(other).leading_zeros()
+    }
+
     fn saturating_add(&self, rhs: &Self) -> Self {
         let mut result = GUID::default();
         let mut carry = 0;
@@ -27,23 +63,11 @@ impl GUID {
         let bytes_b = rhs.bytes;
         let bytes_c = &mut result.bytes;
 
-        #[cfg(target_pointer_width = "64")]
-        {
-            carry = add_carry(carry, bytes_a[2], bytes_b[2], &mut bytes_c[2]);
-            carry = add_carry(carry, bytes_a[1], bytes_b[1], &mut bytes_c[1]);
-            carry = add_carry(carry, bytes_a[0], bytes_b[0], &mut bytes_c[0]);
-        }
-        #[cfg(not(target_pointer_width = "64"))]
-        {
-            carry = add_carry(carry, bytes_a[5], bytes_b[5], &mut bytes_c[5]);
-            carry = add_carry(carry, bytes_a[4], bytes_b[4], &mut bytes_c[4]);
-            carry = add_carry(carry, bytes_a[3], bytes_b[3], &mut bytes_c[3]);
-            carry = add_carry(carry, bytes_a[2], bytes_b[2], &mut bytes_c[2]);
-            carry = add_carry(carry, bytes_a[1], bytes_b[1], &mut bytes_c[1]);
-            carry = add_carry(carry, bytes_a[0], bytes_b[0], &mut bytes_c[0]);
+        for i in (0..WORD_COUNT).rev() {
+            carry = add_carry(carry, bytes_a[i], bytes_b[i], &mut bytes_c[i]);
         }
 
-        if carry > 0 {
+        if carry > 0 || result.bytes[0] > TOP_WORD_MASK {
             GUID::MAX
         } else {
             result
@@ -58,20 +82,8 @@ impl GUID {
         let bytes_b = rhs.bytes;
         let bytes_c = &mut result.bytes;
 
-        #[cfg(target_pointer_width = "64")]
-        {
-            carry = sub_carry(carry, bytes_a[2], bytes_b[2], &mut bytes_c[2]);
-            carry = sub_carry(carry, bytes_a[1], bytes_b[1], &mut bytes_c[1]);
-            carry = sub_carry(carry, bytes_a[0], bytes_b[0], &mut bytes_c[0]);
-        }
-        #[cfg(not(target_pointer_width = "64"))]
-        {
-            carry = sub_carry(carry, bytes_a[5], bytes_b[5], &mut bytes_c[5]);
-            carry = sub_carry(carry, bytes_a[4], bytes_b[4], &mut bytes_c[4]);
-            carry = sub_carry(carry, bytes_a[3], bytes_b[3], &mut bytes_c[3]);
-            carry = sub_carry(carry, bytes_a[2], bytes_b[2], &mut bytes_c[2]);
-            carry = sub_carry(carry, bytes_a[1], bytes_b[1], &mut bytes_c[1]);
-            carry = sub_carry(carry, bytes_a[0], bytes_b[0], &mut bytes_c[0]);
+        for i in (0..WORD_COUNT).rev() {
+            carry = sub_carry(carry, bytes_a[i], bytes_b[i], &mut bytes_c[i]);
         }
 
         if carry > 0 {
@@ -81,10 +93,36 @@ impl GUID {
         }
     }
 
-    fn from_bytes_be(bytes: &[u8]) -> Self {
-        let word_size = size_of::<t_word>();
+    /// Builds a GUID from the output of a [`GuidHasher`]. Since the hasher
+    /// output is exactly [`GUID_BYTES`] long, no bits are lost.
+    pub fn from_blake2b(hasher: GuidHasher) -> Self {
+        Self::from_bytes_be(&hasher.finalize())
+    }
+
+    pub fn to_bytes_be(&self) -> [u8; GUID_BYTES] {
+        let mut bytes = [0; GUID_BYTES];
+
+        for (i, byte) in bytes.iter_mut().rev().enumerate() {
+            let offset = i * 8;
+            let j = WORD_COUNT - 1 - offset / t_word::BITS as usize;
+
+            *byte = (self.bytes[j] >> (offset % t_word::BITS as usize)) as u8;
+        }
 
-        assert!(bytes.len() <= WORD_COUNT * word_size);
+        bytes
+    }
+
+    pub fn to_bytes_le(&self) -> [u8; GUID_BYTES] {
+        let mut bytes = self.to_bytes_be();
+        bytes.reverse();
+        bytes
+    }
+
+    /// Builds a GUID from big-endian bytes. Only the last [`GUID_BYTES`] are
+    /// used, any more significant byte is ignored.
+    pub fn from_bytes_be(bytes: &[u8]) -> Self {
+        let word_size = size_of::<t_word>();
+        let bytes = &bytes[bytes.len().saturating_sub(GUID_BYTES)..];
 
         let mut guid = GUID::MIN;
         let mut offset = 0;
@@ -100,10 +138,11 @@ impl GUID {
         guid
     }
 
-    fn from_bytes_le(bytes: &[u8]) -> Self {
+    /// Builds a GUID from little-endian bytes. Only the first [`GUID_BYTES`]
+    /// are used, any more significant byte is ignored.
+    pub fn from_bytes_le(bytes: &[u8]) -> Self {
         let word_size = size_of::<t_word>();
-
-        assert!(bytes.len() <= WORD_COUNT * word_size);
+        let bytes = &bytes[..bytes.len().min(GUID_BYTES)];
 
         let mut guid = GUID::MIN;
         let mut offset = 0;
@@ -119,23 +158,32 @@ impl GUID {
         guid
     }
 
-    fn from_hex_str(hex: &str) -> Result<Self, GuidError> {
+    /// Exactly `2 * GUID_BYTES` lowercase hex digits, zero padded. This is
+    /// how GUIDs are written in every output of the simulator, so that
+    /// they can be joined on.
+    pub fn to_hex(&self) -> String {
+        format!("{self:0width$x}", width = 2 * GUID_BYTES)
+    }
+
+    /// Parses a GUID from hex digits, optionally prefixed with `0x`. Leading
+    /// zeros are allowed, but at most `2 * GUID_BYTES` significant digits.
+    pub fn from_hex_str(hex: &str) -> Result<Self, GuidError> {
         ///////////////////////////////////////////////////////////////////////
         //////////////////////////// START: HELPERS ///////////////////////////
         ///////////////////////////////////////////////////////////////////////
         #[inline(always)]
         fn conv(c: char) -> u8 {
             if c.is_ascii_digit() {
-                c as u8 - '0' as u8
+                c as u8 - b'0'
             } else {
-                c as u8 - 'a' as u8 + 10
+                c as u8 - b'a' + 10
             }
         }
 
         #[inline(always)]
         fn check(c: char) -> Result<(), GuidError> {
             if !c.is_ascii_hexdigit() {
-                return Err(GuidError::HexFormatInvalid);
+                Err(GuidError::HexFormatInvalid)
             } else {
                 Ok(())
             }
@@ -144,20 +192,26 @@ impl GUID {
         ///////////////////////////// END: HELPERS ////////////////////////////
         ///////////////////////////////////////////////////////////////////////
 
+        let hex = hex.strip_prefix("0x").unwrap_or(hex).to_lowercase();
+
         if hex.is_empty() {
             return Err(GuidError::HexFormatEmpty);
         }
+        // Validated upfront so that every char is a single byte
+        hex.chars().try_for_each(check)?;
 
-        let n = hex.find("0x").map(|n| n + 2).unwrap_or_default();
-        let hex = &hex.to_lowercase()[n..];
-        let n = hex.find(|c| c != '0').map(|n| n).unwrap_or_default();
-
+        let n = hex.find(|c| c != '0').unwrap_or(hex.len());
         let len = hex.len() - n;
+
+        if len > 2 * GUID_BYTES {
+            return Err(GuidError::HexFormatTooLong);
+        }
+
         let mut iter = hex.chars().skip(n);
         let mut bytes = vec![0; len / 2 + len % 2];
         let mut i = 0;
 
-        if len % 2 != 0 {
+        if !len.is_multiple_of(2) {
             if let Some(c_a) = iter.next() {
                 check(c_a)?;
 
@@ -208,6 +262,140 @@ impl std::ops::SubAssign for GUID {
     }
 }
 
+impl std::ops::BitXor for GUID {
+    type Output = Self;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        let mut result = self;
+        result ^= rhs;
+        result
+    }
+}
+
+impl std::ops::BitXorAssign for GUID {
+    fn bitxor_assign(&mut self, rhs: Self) {
+        for (a, b) in self.bytes.iter_mut().zip(rhs.bytes) {
+            *a ^= b;
+        }
+    }
+}
+
+impl std::ops::BitAnd for GUID {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        let mut result = self;
+        result &= rhs;
+        result
+    }
+}
+
+impl std::ops::BitAndAssign for GUID {
+    fn bitand_assign(&mut self, rhs: Self) {
+        for (a, b) in self.bytes.iter_mut().zip(rhs.bytes) {
+            *a &= b;
+        }
+    }
+}
+
+impl std::ops::BitOr for GUID {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        let mut result = self;
+        result |= rhs;
+        result
+    }
+}
+
+impl std::ops::BitOrAssign for GUID {
+    fn bitor_assign(&mut self, rhs: Self) {
+        for (a, b) in self.bytes.iter_mut().zip(rhs.bytes) {
+            *a |= b;
+        }
+    }
+}
+
+impl std::ops::Not for GUID {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        let mut result = self;
+
+        for word in result.bytes.iter_mut() {
+            *word = !*word;
+        }
+        result.bytes[0] &= TOP_WORD_MASK;
+
+        result
+    }
+}
+
+impl std::ops::Shl<u32> for GUID {
+    type Output = Self;
+
+    fn shl(self, rhs: u32) -> Self::Output {
+        let mut result = GUID::MIN;
+
+        if rhs >= GUID_BITS {
+            return result;
+        }
+
+        let words = (rhs / t_word::BITS) as usize;
+        let bits = rhs % t_word::BITS;
+
+        for i in 0..WORD_COUNT - words {
+            let j = i + words;
+            result.bytes[i] = self.bytes[j] << bits;
+
+            if bits > 0 && j + 1 < WORD_COUNT {
+                result.bytes[i] |= self.bytes[j + 1] >> (t_word::BITS - bits);
+            }
+        }
+        result.bytes[0] &= TOP_WORD_MASK;
+
+        result
+    }
+}
+
+impl std::ops::ShlAssign<u32> for GUID {
+    fn shl_assign(&mut self, rhs: u32) {
+        *self = *self << rhs;
+    }
+}
+
+impl std::ops::Shr<u32> for GUID {
+    type Output = Self;
+
+    fn shr(self, rhs: u32) -> Self::Output {
+        let mut result = GUID::MIN;
+
+        if rhs >= GUID_BITS {
+            return result;
+        }
+
+        let words = (rhs / t_word::BITS) as usize;
+        let bits = rhs % t_word::BITS;
+
+        for i in words..WORD_COUNT {
+            let j = i - words;
+            result.bytes[i] = self.bytes[j] >> bits;
+
+            if bits > 0 && j > 0 {
+                result.bytes[i] |= self.bytes[j - 1] << (t_word::BITS - bits);
+            }
+        }
+
+        result
+    }
+}
+
+impl std::ops::ShrAssign<u32> for GUID {
+    fn shr_assign(&mut self, rhs: u32) {
+        *self = *self >> rhs;
+    }
+}
+
 impl std::fmt::UpperHex for GUID {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut hex = String::new();
@@ -261,74 +449,21 @@ impl std::fmt::LowerHex for GUID {
         ///////////////////////////////////////////////////////////////////////
 
         let target: t_word = 1 << (t_word::BITS - 3);
-        let bytes = self.bytes;
-
-        #[cfg(target_pointer_width = "64")]
-        return {
-            if bytes[0] > 0 {
-                write!(
-                    f,
-                    "{:x}{}{}",
-                    bytes[0],
-                    hex(bytes[1], target),
-                    hex(bytes[2], target)
-                )
-            } else if bytes[1] > 0 {
-                write!(f, "{:x}{}", bytes[1], hex(bytes[2], target))
-            } else if bytes[2] > 0 {
-                write!(f, "{:x}", bytes[2])
-            } else {
-                write!(f, "0")
-            }
-        };
-        #[cfg(not(target_pointer_width = "64"))]
-        return {
-            if bytes[0] > 0 {
-                write!(
-                    f,
-                    "{:x}{}{}{}{}{}",
-                    bytes[0],
-                    hex(bytes[1], target),
-                    hex(bytes[2], target),
-                    hex(bytes[3], target),
-                    hex(bytes[4], target),
-                    hex(bytes[5], target)
-                )
-            } else if bytes[1] > 0 {
-                write!(
-                    f,
-                    "{:x}{}{}{}{}",
-                    bytes[1],
-                    hex(bytes[2], target),
-                    hex(bytes[3], target),
-                    hex(bytes[4], target),
-                    hex(bytes[5], target)
-                )
-            } else if bytes[2] > 0 {
-                write!(
-                    f,
-                    "{:x}{}{}{}",
-                    bytes[2],
-                    hex(bytes[3], target),
-                    hex(bytes[4], target),
-                    hex(bytes[5], target)
-                )
-            } else if bytes[3] > 0 {
-                write!(
-                    f,
-                    "{:x}{}{}",
-                    bytes[3],
-                    hex(bytes[4], target),
-                    hex(bytes[5], target)
-                )
-            } else if bytes[4] > 0 {
-                write!(f, "{:x}{}", bytes[4], hex(bytes[5], target))
-            } else if bytes[5] > 0 {
-                write!(f, "{:x}", bytes[5])
-            } else {
-                write!(f, "0")
+        let mut iter = self.bytes.iter().skip_while(|b| **b == 0);
+
+        let digits = match iter.next() {
+            Some(byte) => {
+                let mut digits = format!("{byte:x}");
+                for byte in iter {
+                    digits.push_str(&hex(*byte, target));
+                }
+                digits
             }
+            None => "0".to_string(),
         };
+
+        // Honours the width, zero padding and `#` flags like integers do
+        f.pad_integral(true, "0x", &digits)
     }
 }
 
@@ -392,11 +527,21 @@ impl From<u128> for GUID {
     }
 }
 
+/// Uniformly random GUIDs, as used for RPC ids.
+impl Distribution<GUID> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> GUID {
+        let bytes: [u8; GUID_BYTES] = rng.gen();
+        GUID::from_bytes_be(&bytes)
+    }
+}
+
 #[cfg(test)]
 pub mod test {
     use std::mem::size_of;
 
-    use crate::primitives::GuidError;
+    use blake2::Digest;
+
+    use crate::primitives::{GuidError, GuidHasher, GUID_BYTES};
 
     use super::GUID;
 
@@ -569,6 +714,21 @@ pub mod test {
     fn from_hex_invalid() {
         assert_eq!(GUID::from_hex_str(""), Err(GuidError::HexFormatEmpty));
         assert_eq!(GUID::from_hex_str("z"), Err(GuidError::HexFormatInvalid));
+        assert_eq!(GUID::from_hex_str("0x"), Err(GuidError::HexFormatEmpty));
+        assert_eq!(GUID::from_hex_str("10x1"), Err(GuidError::HexFormatInvalid));
+        assert_eq!(GUID::from_hex_str("é"), Err(GuidError::HexFormatInvalid));
+    }
+
+    #[test]
+    fn from_hex_too_long() {
+        let max = "f".repeat(2 * GUID_BYTES);
+        assert_eq!(GUID::from_hex_str(&max), Ok(GUID::MAX));
+        assert_eq!(GUID::from_hex_str(&format!("000{max}")), Ok(GUID::MAX));
+
+        for len in [41, 49] {
+            let hex = format!("1{}", "0".repeat(len - 1));
+            assert_eq!(GUID::from_hex_str(&hex), Err(GuidError::HexFormatTooLong));
+        }
     }
 
     #[test]
@@ -588,6 +748,50 @@ pub mod test {
         assert_eq!(format!("{guid_be:x}"), format!("{:x}", 42));
     }
 
+    #[test]
+    fn to_hex() {
+        let guid = GUID::from(0xabu8);
+
+        assert_eq!(guid.to_hex(), format!("{}ab", "0".repeat(38)));
+        assert_eq!(GUID::MAX.to_hex(), "f".repeat(2 * GUID_BYTES));
+        assert_eq!(format!("{guid:#06x}"), "0x00ab");
+        assert_eq!(format!("{guid:x}"), "ab");
+    }
+
+    #[test]
+    fn from_bytes_long() {
+        let guid_be = GUID::from_bytes_be(&[u8::MAX; 24]);
+        let guid_le = GUID::from_bytes_le(&[u8::MAX; 24]);
+
+        assert_eq!(guid_be, GUID::MAX);
+        assert_eq!(guid_le, GUID::MAX);
+        assert_eq!(guid_be.leading_zeros(), 0);
+    }
+
+    #[test]
+    fn to_bytes() {
+        let guid = GUID::from_hex_str("0102030405060708090a0b0c0d0e0f1011121314").unwrap();
+        let bytes_be = guid.to_bytes_be();
+        let bytes_le = guid.to_bytes_le();
+
+        assert_eq!(bytes_be[0], 0x01);
+        assert_eq!(bytes_be[GUID_BYTES - 1], 0x14);
+        assert_eq!(bytes_le[0], 0x14);
+        assert_eq!(GUID::from_bytes_be(&bytes_be), guid);
+        assert_eq!(GUID::from_bytes_le(&bytes_le), guid);
+        assert_eq!(GUID::MAX.to_bytes_be(), [u8::MAX; GUID_BYTES]);
+    }
+
+    #[test]
+    fn from_blake2b() {
+        let mut hasher = GuidHasher::new();
+        hasher.update(b"p2p-simulator");
+        let digest = hasher.clone().finalize();
+
+        let guid = GUID::from_blake2b(hasher);
+        assert_eq!(guid.to_bytes_be().as_slice(), digest.as_slice());
+    }
+
     #[test]
     fn empty() {
         let guid = GUID::default();
@@ -623,4 +827,84 @@ pub mod test {
         let guid = GUID::from(u128::MAX);
         assert_eq!(format!("{guid:x}"), format!("{:x}", u128::MAX));
     }
+
+    #[test]
+    fn bitxor() {
+        let guid_a = GUID::from_hex_str("f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0f0").unwrap();
+        let guid_b = GUID::from_hex_str("ff00ff00ff00ff00ff00ff00ff00ff00ff").unwrap();
+
+        assert_eq!(
+            format!("{:x}", guid_a ^ guid_b),
+            "ff00ff00ff00ff00ff00ff00ff00ff00f"
+        );
+        assert_eq!(guid_a ^ guid_a, GUID::MIN);
+        assert_eq!(guid_a ^ GUID::MIN, guid_a);
+    }
+
+    #[test]
+    fn bitand_bitor() {
+        let guid_a = GUID::from(0b1100u32);
+        let guid_b = GUID::from(0b1010u32);
+
+        assert_eq!(guid_a & guid_b, GUID::from(0b1000u32));
+        assert_eq!(guid_a | guid_b, GUID::from(0b1110u32));
+        assert_eq!(GUID::MAX & guid_a, guid_a);
+        assert_eq!(GUID::MAX | guid_a, GUID::MAX);
+    }
+
+    #[test]
+    fn not() {
+        assert_eq!(!GUID::MIN, GUID::MAX);
+        assert_eq!(!GUID::MAX, GUID::MIN);
+        assert_eq!(!!GUID::from(42u32), GUID::from(42u32));
+    }
+
+    #[test]
+    fn shl() {
+        assert_eq!(GUID::from(1u32) << 4, GUID::from(16u32));
+        assert_eq!(GUID::from(1u32) << 64, GUID::from(1u128 << 64));
+        assert_eq!(
+            format!("{:x}", GUID::from(1u32) << 159),
+            "8000000000000000000000000000000000000000"
+        );
+        assert_eq!(GUID::from(1u32) << 160, GUID::MIN);
+        assert_eq!(GUID::MAX << 1, GUID::MAX - GUID::from(1u32));
+    }
+
+    #[test]
+    fn shr() {
+        assert_eq!(GUID::from(16u32) >> 4, GUID::from(1u32));
+        assert_eq!(GUID::from(1u128 << 70) >> 64, GUID::from(64u32));
+        assert_eq!(GUID::MAX >> 159, GUID::from(1u32));
+        assert_eq!(GUID::MAX >> 160, GUID::MIN);
+        assert_eq!((GUID::MAX >> 1).leading_zeros(), 1);
+    }
+
+    #[test]
+    fn distance() {
+        let guid_a = GUID::from(0b0110u32);
+        let guid_b = GUID::from(0b0011u32);
+
+        assert_eq!(guid_a.distance(&guid_b), GUID::from(0b0101u32));
+        assert_eq!(guid_a.distance(&guid_b), guid_b.distance(&guid_a));
+        assert_eq!(guid_a.distance(&guid_a), GUID::MIN);
+    }
+
+    #[test]
+    fn leading_zeros() {
+        assert_eq!(GUID::MIN.leading_zeros(), 160);
+        assert_eq!(GUID::MAX.leading_zeros(), 0);
+        assert_eq!(GUID::from(1u32).leading_zeros(), 159);
+        assert_eq!(GUID::from(u128::MAX).leading_zeros(), 32);
+    }
+
+    #[test]
+    fn common_prefix_len() {
+        let guid_a = GUID::MAX;
+        let guid_b = GUID::MAX >> 1;
+
+        assert_eq!(guid_a.common_prefix_len(&guid_b), 0);
+        assert_eq!(guid_b.common_prefix_len(&GUID::from(1u32)), 1);
+        assert_eq!(guid_a.common_prefix_len(&guid_a), 160);
+    }
 }
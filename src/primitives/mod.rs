@@ -1,21 +1,48 @@
 mod add;
 mod guid;
+#[cfg(feature = "serde")]
+mod serde;
 mod sub;
 
+use blake2::{
+    digest::{consts, typenum::Unsigned},
+    Blake2b,
+};
+
 pub use guid::GUID;
 
+/// Width of a GUID, in bytes. This is the single place where the ID space is
+/// configured: both the GUID arithmetic and [`GuidHasher`] derive from it.
+type GuidWidth = consts::U20;
+
+/// Hasher whose output is exactly one GUID wide.
+pub type GuidHasher = Blake2b<GuidWidth>;
+
+pub const GUID_BYTES: usize = GuidWidth::USIZE;
+pub const GUID_BITS: u32 = GUID_BYTES as u32 * 8;
+
 #[cfg(not(target_pointer_width = "64"))]
-type WordSize = u32;
+#[allow(non_camel_case_types)]
+type t_word = u32;
 #[cfg(target_pointer_width = "64")]
 #[allow(non_camel_case_types)]
 type t_word = u64;
 
-const WORD_COUNT: usize = (160 / t_word::BITS + 1) as usize;
+const WORD_COUNT: usize = GUID_BITS.div_ceil(t_word::BITS) as usize;
+
+/// Number of unused high-order bits in the most significant word.
+const PADDING_BITS: u32 = WORD_COUNT as u32 * t_word::BITS - GUID_BITS;
+/// Mask of the bits of the most significant word which are part of a GUID.
+const TOP_WORD_MASK: t_word = match t_word::MAX.checked_shr(PADDING_BITS) {
+    Some(mask) => mask,
+    None => 0,
+};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum GuidError {
     HexFormatInvalid,
     HexFormatEmpty,
+    HexFormatTooLong,
 }
 
 impl std::fmt::Display for GuidError {
@@ -39,6 +66,15 @@ impl std::fmt::Display for GuidError {
                     )
                 )
             }
+            GuidError::HexFormatTooLong => {
+                write!(
+                    f,
+                    concat!(
+                        "Invalid hex format, ",
+                        "a GUID is at most 40 significant hex digits long"
+                    )
+                )
+            }
         }
     }
 }
@@ -0,0 +1,105 @@
+use serde::{
+    de::{Error, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::{GUID, GUID_BYTES};
+
+/// Human-readable formats get the GUID as exactly `2 * GUID_BYTES` lowercase
+/// hex digits, binary formats as its raw big-endian bytes.
+impl Serialize for GUID {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_hex())
+        } else {
+            serializer.serialize_bytes(&self.to_bytes_be())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for GUID {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(GuidVisitor)
+        } else {
+            deserializer.deserialize_bytes(GuidVisitor)
+        }
+    }
+}
+
+struct GuidVisitor;
+
+impl<'de> Visitor<'de> for GuidVisitor {
+    type Value = GUID;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} hex digits or {GUID_BYTES} bytes", 2 * GUID_BYTES)
+    }
+
+    fn visit_str<E: Error>(self, hex: &str) -> Result<GUID, E> {
+        if hex.len() != 2 * GUID_BYTES {
+            return Err(E::invalid_length(hex.len(), &self));
+        }
+
+        GUID::from_hex_str(hex).map_err(E::custom)
+    }
+
+    fn visit_bytes<E: Error>(self, bytes: &[u8]) -> Result<GUID, E> {
+        if bytes.len() != GUID_BYTES {
+            return Err(E::invalid_length(bytes.len(), &self));
+        }
+
+        Ok(GUID::from_bytes_be(bytes))
+    }
+
+    /// Formats without native byte strings encode bytes as a sequence.
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<GUID, A::Error> {
+        let mut bytes = [0; GUID_BYTES];
+
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = seq
+                .next_element()?
+                .ok_or_else(|| A::Error::invalid_length(i, &self))?;
+        }
+        if seq.next_element::<u8>()?.is_some() {
+            return Err(A::Error::invalid_length(GUID_BYTES + 1, &self));
+        }
+
+        Ok(GUID::from_bytes_be(&bytes))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use serde::de::{value::Error, Visitor};
+
+    use crate::primitives::{GUID, GUID_BYTES};
+
+    use super::GuidVisitor;
+
+    #[test]
+    fn fixed_width_hex() {
+        let guid = GUID::from(0xabcu16);
+        let json = serde_json::to_string(&guid).unwrap();
+
+        assert_eq!(json, format!("\"{}abc\"", "0".repeat(37)));
+        assert_eq!(serde_json::from_str::<GUID>(&json).unwrap(), guid);
+        assert_eq!(
+            serde_json::from_str::<GUID>(&format!("\"{:x}\"", GUID::MAX)).unwrap(),
+            GUID::MAX
+        );
+
+        assert!(serde_json::from_str::<GUID>("\"abc\"").is_err());
+        assert!(serde_json::from_str::<GUID>(&format!("\"{}\"", "g".repeat(40))).is_err());
+    }
+
+    #[test]
+    fn raw_bytes() {
+        let guid = GUID::from(0x0102u16);
+        let bytes = guid.to_bytes_be();
+
+        assert_eq!(bytes[GUID_BYTES - 2..], [1, 2]);
+        assert_eq!(GuidVisitor.visit_bytes::<Error>(&bytes), Ok(guid));
+        assert!(GuidVisitor.visit_bytes::<Error>(&bytes[1..]).is_err());
+    }
+}
@@ -7,6 +7,7 @@ use core::arch::x86_64 as arch;
 #[cfg(target_arch = "x86")]
 #[cfg(not(target_pointer_width = "64"))]
 #[inline]
+#[allow(unused_unsafe)]
 pub(super) fn sub_carry(c_in: u8, a: u32, b: u32, out: &mut u32) -> u8 {
     unsafe { arch::_subborrow_u32(c_in, a, b, out) }
 }
@@ -14,15 +15,17 @@ pub(super) fn sub_carry(c_in: u8, a: u32, b: u32, out: &mut u32) -> u8 {
 #[cfg(target_arch = "x86_64")]
 #[cfg(target_pointer_width = "64")]
 #[inline]
+#[allow(unused_unsafe)]
 pub(super) fn sub_carry(c_in: u8, a: u64, b: u64, out: &mut u64) -> u8 {
     unsafe { arch::_subborrow_u64(c_in, a, b, out) }
 }
 
 #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
-#[inline]
-pub(super) fn sub_carry(c_in: u8, a: u32, b: u32, out: &mut u32) -> u8 {
-    use super::t_word;
+use super::t_word;
 
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+#[inline]
+pub(super) fn sub_carry(c_in: u8, a: t_word, b: t_word, out: &mut t_word) -> u8 {
     let (a, b) = a.overflowing_sub(b);
     let (c, d) = a.overflowing_sub(c_in as t_word);
     *out = c;
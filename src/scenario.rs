@@ -0,0 +1,956 @@
+use std::path::{Path, PathBuf};
+
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    network::{Address, Network},
+    node::{Contact, LookupConfig, Operation, ProtocolConfig, K, MAX_VALUE_LEN},
+    primitives::{GuidError, GUID, GUID_BITS},
+    simulator::{
+        ChurnError, ChurnModel, ChurnTrace, ConstantLatency, Departure, Event, LatencyError,
+        LatencyMatrix, LinkFaults, LinkModel, LogNormalLatency, Partition, SessionDistribution,
+        Simulator, SimulatorConfig, Time, UniformLatency,
+    },
+};
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(std::io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    /// The file extension is neither `.toml` nor `.json`.
+    FormatUnknown {
+        path: PathBuf,
+    },
+    Latency(LatencyError),
+    Churn(ChurnError),
+    /// A timeline step refers to a node index past the initial node count.
+    NodeUnknown {
+        index: usize,
+    },
+    KeyInvalid {
+        key: String,
+        err: GuidError,
+    },
+    /// A parameter is out of range.
+    ParameterInvalid {
+        name: &'static str,
+    },
+    /// Churn and maintenance never run out of events, so the scenario needs
+    /// a duration.
+    DurationMissing,
+}
+
+impl std::fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScenarioError::Io(err) => write!(f, "Failed to read scenario: {err}"),
+            ScenarioError::Toml(err) => write!(f, "Invalid scenario: {err}"),
+            ScenarioError::Json(err) => write!(f, "Invalid scenario: {err}"),
+            ScenarioError::FormatUnknown { path } => write!(
+                f,
+                "Unknown scenario format for {}, expected a .toml or .json file",
+                path.display()
+            ),
+            ScenarioError::Latency(err) => write!(f, "{err}"),
+            ScenarioError::Churn(err) => write!(f, "{err}"),
+            ScenarioError::NodeUnknown { index } => write!(f, "Unknown node {index}"),
+            ScenarioError::KeyInvalid { key, err } => write!(f, "Invalid key {key:?}: {err}"),
+            ScenarioError::ParameterInvalid { name } => write!(f, "Invalid {name}"),
+            ScenarioError::DurationMissing => {
+                write!(f, "Scenarios with churn or maintenance need a duration")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+impl From<std::io::Error> for ScenarioError {
+    fn from(err: std::io::Error) -> Self {
+        ScenarioError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for ScenarioError {
+    fn from(err: toml::de::Error) -> Self {
+        ScenarioError::Toml(err)
+    }
+}
+
+impl From<serde_json::Error> for ScenarioError {
+    fn from(err: serde_json::Error) -> Self {
+        ScenarioError::Json(err)
+    }
+}
+
+impl From<LatencyError> for ScenarioError {
+    fn from(err: LatencyError) -> Self {
+        ScenarioError::Latency(err)
+    }
+}
+
+impl From<ChurnError> for ScenarioError {
+    fn from(err: ChurnError) -> Self {
+        ScenarioError::Churn(err)
+    }
+}
+
+/// Declarative description of an experiment, loaded from a TOML or JSON
+/// file.
+///
+/// Times are in seconds, and latencies in milliseconds. Nodes are referred
+/// to by their index among the `nodes` initial nodes, which all know of each
+/// other when the simulation starts.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub seed: u64,
+    pub nodes: usize,
+    #[serde(default)]
+    pub ids: IdDistribution,
+    /// Replication factor, which cannot exceed the bucket size [`K`].
+    #[serde(default = "default_k")]
+    pub k: usize,
+    #[serde(default = "default_alpha")]
+    pub alpha: usize,
+    /// How long to simulate for. Without it, the simulation runs until no
+    /// events are left.
+    pub duration: Option<f64>,
+    /// Whether nodes periodically refresh their buckets and republish their
+    /// values.
+    #[serde(default)]
+    pub maintenance: bool,
+    #[serde(default)]
+    pub latency: LatencySpec,
+    #[serde(default)]
+    pub links: LinkSpec,
+    pub churn: Option<ChurnSpec>,
+    #[serde(default)]
+    pub timeline: Vec<Step>,
+    /// Conditions which must hold once the scenario has run, see
+    /// [`Scenario::check`].
+    #[serde(default, rename = "assert")]
+    pub assertions: Vec<Assertion>,
+}
+
+fn default_k() -> usize {
+    LookupConfig::default().k
+}
+
+fn default_alpha() -> usize {
+    LookupConfig::default().alpha
+}
+
+/// How the GUIDs of the initial nodes are picked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(tag = "distribution", rename_all = "snake_case", deny_unknown_fields)]
+pub enum IdDistribution {
+    /// Uniformly random GUIDs.
+    #[default]
+    Uniform,
+    /// GUIDs `0`, `1`, `2`... which all share their leading bits.
+    Sequential,
+    /// Random GUIDs which share their first `bits` bits.
+    Clustered { bits: u32 },
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LatencySpec {
+    Constant {
+        ms: f64,
+    },
+    Uniform {
+        min_ms: f64,
+        max_ms: f64,
+    },
+    LogNormal {
+        median_ms: f64,
+        sigma: f64,
+    },
+    /// King-style matrix of round-trip times, see [`LatencyMatrix::from_csv`].
+    Matrix {
+        path: PathBuf,
+    },
+}
+
+impl Default for LatencySpec {
+    fn default() -> Self {
+        LatencySpec::Constant {
+            ms: ConstantLatency::default().0.as_secs_f64() * 1_000.0,
+        }
+    }
+}
+
+/// Faults applied to every link, see [`LinkFaults`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkSpec {
+    pub drop: f64,
+    pub duplicate: f64,
+    pub delay: f64,
+    pub delay_ms: f64,
+    pub reorder: f64,
+    pub reorder_window_ms: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ChurnSpec {
+    /// See [`ChurnModel`].
+    Model {
+        session: SessionSpec,
+        downtime: SessionSpec,
+        #[serde(default)]
+        graceful: f64,
+    },
+    /// See [`ChurnTrace::from_csv`].
+    Trace { path: PathBuf },
+}
+
+/// See [`SessionDistribution`], in seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(tag = "distribution", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SessionSpec {
+    Exponential { mean: f64 },
+    Weibull { scale: f64, shape: f64 },
+    Pareto { scale: f64, shape: f64 },
+}
+
+/// A [`Command`] carried out `at` a given time.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Step {
+    pub at: f64,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+/// Something which happens on the timeline. Keys are hex GUIDs, and data is
+/// stored as the bytes of a string.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Command {
+    Store {
+        node: usize,
+        key: String,
+        data: String,
+    },
+    Query {
+        node: usize,
+        key: String,
+    },
+    FindNode {
+        node: usize,
+        target: String,
+    },
+    /// Splits the network into `groups`, with the nodes left out forming one
+    /// more group. Lasts `until` a given time if set, or until the
+    /// next `heal`.
+    Partition {
+        groups: Vec<Vec<usize>>,
+        until: Option<f64>,
+    },
+    Heal,
+    /// Takes a node out of the network, handing its values off first if
+    /// `graceful`.
+    Kill {
+        node: usize,
+        #[serde(default)]
+        graceful: bool,
+    },
+    /// Adds `count` nodes, each joining through a random node.
+    Join {
+        #[serde(default = "default_count")]
+        count: usize,
+    },
+    /// Records how far the network has diverged across the last partition.
+    Measure,
+}
+
+fn default_count() -> usize {
+    1
+}
+
+/// Condition on the state of the simulation at the end of a scenario.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "check", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Assertion {
+    /// Some node still holds the value stored under `key`.
+    Stored { key: String },
+    /// Every key stored by a `store` is still held by some node.
+    NoLostKeys,
+    /// At least `ratio` of the queries found their value.
+    QueriesSucceed {
+        #[serde(default = "default_ratio")]
+        ratio: f64,
+    },
+    /// No lookup followed more than `hops` hops.
+    MaxHops { hops: usize },
+    /// The network ends up with between `min` and `max` nodes.
+    NetworkSize {
+        min: Option<usize>,
+        max: Option<usize>,
+    },
+}
+
+fn default_ratio() -> f64 {
+    1.0
+}
+
+/// An [`Assertion`] which does not hold, and why.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssertionFailure {
+    pub assertion: Assertion,
+    pub reason: String,
+}
+
+impl std::fmt::Display for AssertionFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} failed: {}", self.assertion, self.reason)
+    }
+}
+
+impl Scenario {
+    /// Scenario with `nodes` nodes, default parameters and an empty timeline.
+    pub fn new(nodes: usize) -> Self {
+        Self {
+            seed: 0,
+            nodes,
+            ids: IdDistribution::default(),
+            k: default_k(),
+            alpha: default_alpha(),
+            duration: None,
+            maintenance: false,
+            latency: LatencySpec::default(),
+            links: LinkSpec::default(),
+            churn: None,
+            timeline: Vec::new(),
+            assertions: Vec::new(),
+        }
+    }
+
+    /// Loads a scenario, in TOML or JSON depending on the extension of
+    /// `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ScenarioError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml(&text),
+            Some("json") => Self::from_json(&text),
+            _ => Err(ScenarioError::FormatUnknown {
+                path: path.to_path_buf(),
+            }),
+        }
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, ScenarioError> {
+        Ok(toml::from_str(text)?)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, ScenarioError> {
+        Ok(serde_json::from_str(text)?)
+    }
+
+    pub fn config(&self) -> SimulatorConfig {
+        SimulatorConfig {
+            protocol: ProtocolConfig {
+                lookup: LookupConfig {
+                    alpha: self.alpha,
+                    k: self.k,
+                },
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Sets up a simulator for the scenario, with its whole timeline
+    /// scheduled but nothing run yet.
+    pub fn build(&self) -> Result<Simulator, ScenarioError> {
+        self.validate()?;
+
+        let mut network = Network::new(self.seed);
+        let contacts = self.ids.spawn(&mut network, self.nodes);
+
+        for a in contacts.iter() {
+            for b in contacts.iter() {
+                network.connect(a.address, *b);
+            }
+        }
+
+        let mut sim = Simulator::new(network, self.config()).with_links(self.links.model());
+        sim = match &self.latency {
+            LatencySpec::Constant { ms } => sim.with_latency(ConstantLatency(millis(*ms))),
+            LatencySpec::Uniform { min_ms, max_ms } => sim.with_latency(UniformLatency {
+                min: millis(*min_ms),
+                max: millis(*max_ms),
+            }),
+            LatencySpec::LogNormal { median_ms, sigma } => {
+                sim.with_latency(LogNormalLatency::new(millis(*median_ms), *sigma)?)
+            }
+            LatencySpec::Matrix { path } => sim.with_latency(LatencyMatrix::from_file(path)?),
+        };
+
+        if self.maintenance {
+            sim.maintain();
+        }
+
+        match &self.churn {
+            Some(ChurnSpec::Model {
+                session,
+                downtime,
+                graceful,
+            }) => sim.churn(ChurnModel {
+                session: session.distribution()?,
+                downtime: downtime.distribution()?,
+                graceful: *graceful,
+            }),
+            Some(ChurnSpec::Trace { path }) => sim.replay(&ChurnTrace::from_file(path)?),
+            None => {}
+        }
+
+        let mut joined = 0;
+        for step in self.timeline.iter() {
+            let at = Time::from_secs_f64(step.at);
+
+            for event in step.command.events(&contacts, &mut joined)? {
+                sim.schedule(at, event);
+            }
+
+            if let Command::Partition {
+                until: Some(until), ..
+            } = step.command
+            {
+                sim.schedule(Time::from_secs_f64(until), Event::Heal);
+            }
+        }
+
+        Ok(sim)
+    }
+
+    /// Builds the scenario and runs it to the end.
+    pub fn run(&self) -> Result<Simulator, ScenarioError> {
+        let mut sim = self.build()?;
+        self.play(&mut sim);
+
+        Ok(sim)
+    }
+
+    /// Runs `sim`, as set up by [`Scenario::build`], for the duration of the
+    /// scenario.
+    pub fn play(&self, sim: &mut Simulator) {
+        match self.duration {
+            Some(duration) => sim.run_until(Time::from_secs_f64(duration)),
+            None => sim.run(),
+        }
+    }
+
+    fn validate(&self) -> Result<(), ScenarioError> {
+        let invalid = |name| Err(ScenarioError::ParameterInvalid { name });
+
+        if self.nodes == 0 {
+            return invalid("node count");
+        }
+        if self.k == 0 || self.k > K {
+            return invalid("k");
+        }
+        if self.alpha == 0 {
+            return invalid("alpha");
+        }
+        self.ids.validate()?;
+        // Also rejects NaN, which `[0, 1]` does not contain
+        let probability = |p: f64| (0.0..=1.0).contains(&p);
+        let links = [
+            ("drop probability", self.links.drop),
+            ("duplicate probability", self.links.duplicate),
+            ("delay probability", self.links.delay),
+            ("reorder probability", self.links.reorder),
+        ];
+        for (name, p) in links {
+            if !probability(p) {
+                return invalid(name);
+            }
+        }
+        if let Some(ChurnSpec::Model { graceful, .. }) = self.churn {
+            if !probability(graceful) {
+                return invalid("graceful probability");
+            }
+        }
+        if self.duration.is_none() && (self.maintenance || self.churn.is_some()) {
+            return Err(ScenarioError::DurationMissing);
+        }
+        for step in self.timeline.iter() {
+            // Larger values do not fit in a frame, and would never be sent
+            if let Command::Store { data, .. } = &step.command {
+                if data.len() > MAX_VALUE_LEN {
+                    return invalid("store data length");
+                }
+            }
+        }
+        for assertion in self.assertions.iter() {
+            if let Assertion::Stored { key } = assertion {
+                parse_key(key)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks every assertion of the scenario against `sim`, once it has
+    /// run, returning those which do not hold.
+    pub fn check(&self, sim: &Simulator) -> Result<Vec<AssertionFailure>, ScenarioError> {
+        let mut failures = Vec::new();
+
+        for assertion in self.assertions.iter() {
+            if let Some(reason) = assertion.check(sim)? {
+                failures.push(AssertionFailure {
+                    assertion: assertion.clone(),
+                    reason,
+                });
+            }
+        }
+
+        Ok(failures)
+    }
+}
+
+impl IdDistribution {
+    pub fn validate(&self) -> Result<(), ScenarioError> {
+        if let IdDistribution::Clustered { bits } = self {
+            // Leaves enough random bits for GUIDs not to collide
+            if *bits > GUID_BITS - 64 {
+                return Err(ScenarioError::ParameterInvalid {
+                    name: "cluster prefix length",
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds `count` nodes to `network`, with GUIDs following the
+    /// distribution. GUIDs already taken in `network` are skipped.
+    pub fn spawn(&self, network: &mut Network, count: usize) -> Vec<Contact> {
+        let suffix = match self {
+            IdDistribution::Clustered { bits } => GUID::MAX >> *bits,
+            _ => GUID::MAX,
+        };
+        let prefix = network.rng().gen::<GUID>() & !suffix;
+        let mut next = 0u64;
+
+        (0..count)
+            .map(|i| match self {
+                IdDistribution::Uniform => network.spawn(&format!("node-{i}")),
+                IdDistribution::Sequential => loop {
+                    let guid = GUID::from(next);
+                    next += 1;
+                    if let Some(contact) = network.spawn_with_guid(guid, &[]) {
+                        break contact;
+                    }
+                },
+                IdDistribution::Clustered { .. } => loop {
+                    let guid = prefix | (network.rng().gen::<GUID>() & suffix);
+                    if let Some(contact) = network.spawn_with_guid(guid, &[]) {
+                        break contact;
+                    }
+                },
+            })
+            .collect()
+    }
+}
+
+impl LinkSpec {
+    pub fn model(&self) -> LinkModel {
+        LinkModel::new(LinkFaults {
+            drop: self.drop,
+            duplicate: self.duplicate,
+            delay: self.delay,
+            delay_by: millis(self.delay_ms),
+            reorder: self.reorder,
+            reorder_window: millis(self.reorder_window_ms),
+        })
+    }
+}
+
+impl SessionSpec {
+    pub fn distribution(&self) -> Result<SessionDistribution, ChurnError> {
+        match *self {
+            SessionSpec::Exponential { mean } => {
+                SessionDistribution::exponential(Time::from_secs_f64(mean))
+            }
+            SessionSpec::Weibull { scale, shape } => {
+                SessionDistribution::weibull(Time::from_secs_f64(scale), shape)
+            }
+            SessionSpec::Pareto { scale, shape } => {
+                SessionDistribution::pareto(Time::from_secs_f64(scale), shape)
+            }
+        }
+    }
+}
+
+impl Command {
+    /// Events carrying out the command on the initial nodes `contacts`.
+    /// `joined` counts the nodes added so far, to name new ones.
+    fn events(
+        &self,
+        contacts: &[Contact],
+        joined: &mut usize,
+    ) -> Result<Vec<Event>, ScenarioError> {
+        let node = |index: usize| {
+            contacts
+                .get(index)
+                .map(|contact| contact.address)
+                .ok_or(ScenarioError::NodeUnknown { index })
+        };
+        let operation = |index, operation| -> Result<_, ScenarioError> {
+            Ok(vec![Event::Operation {
+                address: node(index)?,
+                operation,
+            }])
+        };
+
+        match self {
+            Command::Store { node, key, data } => operation(
+                *node,
+                Operation::Store {
+                    key: parse_key(key)?,
+                    data: data.as_bytes().to_vec(),
+                },
+            ),
+            Command::Query { node, key } => operation(
+                *node,
+                Operation::Query {
+                    key: parse_key(key)?,
+                },
+            ),
+            Command::FindNode { node, target } => operation(
+                *node,
+                Operation::FindNode {
+                    target: parse_key(target)?,
+                },
+            ),
+            Command::Partition { groups, .. } => {
+                let groups = groups
+                    .iter()
+                    .map(|group| group.iter().map(|i| node(*i)).collect())
+                    .collect::<Result<Vec<Vec<Address>>, _>>()?;
+
+                Ok(vec![Event::Partition {
+                    partition: Partition::new(groups),
+                }])
+            }
+            Command::Heal => Ok(vec![Event::Heal]),
+            Command::Kill {
+                node: index,
+                graceful,
+            } => Ok(vec![Event::Leave {
+                address: node(*index)?,
+                departure: if *graceful {
+                    Departure::Graceful
+                } else {
+                    Departure::Crash
+                },
+            }]),
+            Command::Join { count } => Ok((0..*count)
+                .map(|_| {
+                    *joined += 1;
+                    Event::Join {
+                        name: format!("joined-{joined}"),
+                        peers: Vec::new(),
+                    }
+                })
+                .collect()),
+            Command::Measure => Ok(vec![Event::Measure]),
+        }
+    }
+}
+
+impl Assertion {
+    /// Why the assertion does not hold for `sim`, if it does not.
+    fn check(&self, sim: &Simulator) -> Result<Option<String>, ScenarioError> {
+        let failure = match self {
+            Assertion::Stored { key } => {
+                let guid = parse_key(key)?;
+                let stored = sim
+                    .network()
+                    .nodes()
+                    .any(|node| node.storage().contains_key(&guid));
+
+                (!stored).then(|| format!("no node holds {key}"))
+            }
+            Assertion::NoLostKeys => {
+                let lost = sim.lost_keys();
+                (!lost.is_empty()).then(|| format!("{} keys were lost", lost.len()))
+            }
+            Assertion::QueriesSucceed { ratio } => {
+                let queries = sim
+                    .completed()
+                    .iter()
+                    .filter(|result| matches!(result.operation, Operation::Query { .. }));
+                let (found, total) = queries.fold((0, 0), |(found, total), result| {
+                    (found + result.lookup.value.is_some() as usize, total + 1)
+                });
+                let actual = if total == 0 {
+                    1.0
+                } else {
+                    found as f64 / total as f64
+                };
+
+                (actual < *ratio).then(|| format!("{found} of {total} queries succeeded"))
+            }
+            Assertion::MaxHops { hops } => {
+                let max = sim
+                    .completed()
+                    .iter()
+                    .map(|result| result.lookup.hops)
+                    .max()
+                    .unwrap_or_default();
+
+                (max > *hops).then(|| format!("a lookup took {max} hops"))
+            }
+            Assertion::NetworkSize { min, max } => {
+                let len = sim.network().len();
+                let too_small = min.is_some_and(|min| len < min);
+                let too_large = max.is_some_and(|max| len > max);
+
+                (too_small || too_large).then(|| format!("the network has {len} nodes"))
+            }
+        };
+
+        Ok(failure)
+    }
+}
+
+fn parse_key(key: &str) -> Result<GUID, ScenarioError> {
+    GUID::from_hex_str(key).map_err(|err| ScenarioError::KeyInvalid {
+        key: key.to_string(),
+        err,
+    })
+}
+
+fn millis(ms: f64) -> Time {
+    Time::from_secs_f64(ms / 1_000.0)
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        network::Network,
+        node::{Operation, MAX_VALUE_LEN},
+        primitives::GUID,
+        simulator::Time,
+    };
+
+    use super::{
+        Assertion, ChurnSpec, Command, IdDistribution, LatencySpec, LinkSpec, Scenario,
+        ScenarioError, SessionSpec, Step,
+    };
+
+    const EXAMPLE: &str = include_str!("../scenarios/example.toml");
+
+    #[test]
+    fn parse_toml() {
+        let scenario = Scenario::from_toml(EXAMPLE).unwrap();
+
+        assert_eq!(scenario.nodes, 50);
+        assert_eq!(scenario.ids, IdDistribution::Uniform);
+        assert!(matches!(scenario.latency, LatencySpec::LogNormal { .. }));
+        assert_eq!(scenario.links.drop, 0.01);
+        assert_eq!(
+            scenario.timeline[0].command,
+            Command::Store {
+                node: 0,
+                key: "0x2a".to_string(),
+                data: "hello".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn parse_json() {
+        let scenario = Scenario::from_json(
+            r#"{
+                "nodes": 10,
+                "ids": { "distribution": "clustered", "bits": 8 },
+                "timeline": [
+                    { "at": 1.5, "action": "kill", "node": 3, "graceful": true },
+                    { "at": 2, "action": "join", "count": 2 }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(scenario.ids, IdDistribution::Clustered { bits: 8 });
+        assert_eq!(scenario.k, 20);
+        assert_eq!(scenario.timeline.len(), 2);
+        assert_eq!(
+            scenario.timeline[0].command,
+            Command::Kill {
+                node: 3,
+                graceful: true
+            }
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let unknown_node = Scenario::from_toml(
+            r#"
+            nodes = 2
+            [[timeline]]
+            at = 0
+            action = "query"
+            node = 2
+            key = "0x1"
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            unknown_node.build(),
+            Err(ScenarioError::NodeUnknown { index: 2 })
+        ));
+
+        let endless = Scenario::from_toml("nodes = 2\nmaintenance = true").unwrap();
+        assert!(matches!(
+            endless.build(),
+            Err(ScenarioError::DurationMissing)
+        ));
+
+        assert!(Scenario::from_toml("nodes = 2\nunknown = 1").is_err());
+
+        let mut oversized = Scenario::new(2);
+        oversized.timeline.push(Step {
+            at: 0.0,
+            command: Command::Store {
+                node: 0,
+                key: "0x1".to_string(),
+                data: "a".repeat(MAX_VALUE_LEN + 1),
+            },
+        });
+        assert!(matches!(
+            oversized.build(),
+            Err(ScenarioError::ParameterInvalid {
+                name: "store data length"
+            })
+        ));
+    }
+
+    #[test]
+    fn probabilities() {
+        let invalid = |scenario: &Scenario| match scenario.build() {
+            Err(ScenarioError::ParameterInvalid { name }) => name,
+            _ => panic!("Expected an invalid parameter"),
+        };
+        let none = LinkSpec::default();
+
+        for p in [-0.1, 1.5, f64::NAN, f64::INFINITY] {
+            let links = [
+                ("drop probability", LinkSpec { drop: p, ..none }),
+                (
+                    "duplicate probability",
+                    LinkSpec {
+                        duplicate: p,
+                        ..none
+                    },
+                ),
+                ("delay probability", LinkSpec { delay: p, ..none }),
+                ("reorder probability", LinkSpec { reorder: p, ..none }),
+            ];
+            for (name, links) in links {
+                let mut scenario = Scenario::new(2);
+                scenario.links = links;
+                assert_eq!(invalid(&scenario), name);
+            }
+        }
+
+        for p in [-0.1, 1.5, f64::NAN] {
+            let mut scenario = Scenario::new(2);
+            scenario.duration = Some(60.0);
+            scenario.churn = Some(ChurnSpec::Model {
+                session: SessionSpec::Exponential { mean: 60.0 },
+                downtime: SessionSpec::Exponential { mean: 60.0 },
+                graceful: p,
+            });
+            assert_eq!(invalid(&scenario), "graceful probability");
+        }
+
+        let nan = Scenario::from_toml("nodes = 2\n[links]\ndrop = nan").unwrap();
+        assert_eq!(invalid(&nan), "drop probability");
+    }
+
+    #[test]
+    fn assertions() {
+        let mut scenario = Scenario::from_toml(EXAMPLE).unwrap();
+        let sim = scenario.run().unwrap();
+        assert_eq!(scenario.check(&sim).unwrap(), []);
+
+        scenario.assertions = vec![
+            Assertion::Stored {
+                key: "0x2b".to_string(),
+            },
+            Assertion::NetworkSize {
+                min: Some(60),
+                max: None,
+            },
+        ];
+        let failures = scenario.check(&sim).unwrap();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[1].reason, "the network has 51 nodes");
+    }
+
+    #[test]
+    fn clustered_ids() {
+        let mut network = Network::new(0);
+        let contacts = IdDistribution::Clustered { bits: 16 }.spawn(&mut network, 10);
+
+        assert!(contacts
+            .windows(2)
+            .all(|pair| pair[0].guid.common_prefix_len(&pair[1].guid) >= 16));
+
+        assert!(IdDistribution::Clustered { bits: 96 }.validate().is_ok());
+        assert!(matches!(
+            IdDistribution::Clustered { bits: 97 }.validate(),
+            Err(ScenarioError::ParameterInvalid {
+                name: "cluster prefix length"
+            })
+        ));
+    }
+
+    #[test]
+    fn sequential_ids_skip_taken() {
+        let mut network = Network::new(0);
+        network.spawn_with_guid(GUID::from(1u8), &[]);
+        let contacts = IdDistribution::Sequential.spawn(&mut network, 3);
+
+        let guids = contacts
+            .iter()
+            .map(|contact| contact.guid)
+            .collect::<Vec<_>>();
+        assert_eq!(guids, [0u8, 2, 3].map(GUID::from));
+        assert_eq!(network.len(), 4);
+    }
+
+    #[test]
+    fn run() {
+        let scenario = Scenario::from_toml(EXAMPLE).unwrap();
+        let sim = scenario.run().unwrap();
+        let key = GUID::from(0x2au8);
+
+        assert_eq!(sim.now(), Time::from_secs(120));
+        assert_eq!(sim.network().len(), 50 - 1 + 2);
+        let queries = sim
+            .completed()
+            .iter()
+            .filter(|result| result.operation == Operation::Query { key })
+            .collect::<Vec<_>>();
+        assert_eq!(queries.len(), 2);
+        assert!(queries
+            .iter()
+            .all(|result| result.lookup.value.as_deref() == Some(b"hello".as_slice())));
+        assert_eq!(sim.divergence_history().len(), 2);
+        assert!(sim.lost_keys().iter().all(|lost| *lost != key));
+    }
+}
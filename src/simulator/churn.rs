@@ -0,0 +1,399 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use rand_distr::{Distribution, Exp, Pareto, Weibull};
+
+use crate::codec::{write_tag, Decode, DecodeError, Decoder, Encode};
+
+use super::{SimRng, Time};
+
+/// How a node leaves the network.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Departure {
+    /// The node hands its stored values off to its closest peers first.
+    Graceful,
+    /// The node disappears without notice.
+    Crash,
+}
+
+#[derive(Debug)]
+pub enum ChurnError {
+    Io(std::io::Error),
+    /// A trace line does not follow the expected format.
+    LineInvalid {
+        line: usize,
+    },
+    /// Distribution parameters are out of range.
+    ParameterInvalid,
+}
+
+impl std::fmt::Display for ChurnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChurnError::Io(err) => write!(f, "Failed to read churn trace: {err}"),
+            ChurnError::LineInvalid { line } => write!(
+                f,
+                "Invalid churn trace line {line}, expected `<seconds>,<id>,<up|down>[,<graceful|crash>]`"
+            ),
+            ChurnError::ParameterInvalid => write!(f, "Invalid churn distribution parameter"),
+        }
+    }
+}
+
+impl std::error::Error for ChurnError {}
+
+impl From<std::io::Error> for ChurnError {
+    fn from(err: std::io::Error) -> Self {
+        ChurnError::Io(err)
+    }
+}
+
+/// Distribution of session lengths or downtimes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SessionDistribution(Sessions);
+
+/// Parameters of a [`SessionDistribution`], checked when it is built. They
+/// are kept rather than the distributions themselves, which do not expose
+/// them, so that the churn model can be encoded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Sessions {
+    Exponential { mean: Time },
+    Weibull { scale: Time, shape: f64 },
+    Pareto { scale: Time, shape: f64 },
+}
+
+impl SessionDistribution {
+    /// Memoryless sessions with the given `mean`.
+    pub fn exponential(mean: Time) -> Result<Self, ChurnError> {
+        Self::checked(Sessions::Exponential { mean })
+    }
+
+    /// Weibull distributed sessions. A `shape` below 1 means the longer a
+    /// node has been up, the less likely it is to leave, as measured in
+    /// deployed peer-to-peer networks.
+    pub fn weibull(scale: Time, shape: f64) -> Result<Self, ChurnError> {
+        Self::checked(Sessions::Weibull { scale, shape })
+    }
+
+    /// Heavy-tailed sessions, never shorter than `scale`.
+    pub fn pareto(scale: Time, shape: f64) -> Result<Self, ChurnError> {
+        Self::checked(Sessions::Pareto { scale, shape })
+    }
+
+    fn checked(sessions: Sessions) -> Result<Self, ChurnError> {
+        let valid = match sessions {
+            Sessions::Exponential { mean } => Exp::new(1.0 / mean.as_secs_f64()).is_ok(),
+            Sessions::Weibull { scale, shape } => Weibull::new(scale.as_secs_f64(), shape).is_ok(),
+            Sessions::Pareto { scale, shape } => Pareto::new(scale.as_secs_f64(), shape).is_ok(),
+        };
+
+        if valid {
+            Ok(Self(sessions))
+        } else {
+            Err(ChurnError::ParameterInvalid)
+        }
+    }
+
+    pub fn sample(&self, rng: &mut SimRng) -> Time {
+        const CHECKED: &str = "parameters are checked on construction";
+
+        let secs = match self.0 {
+            Sessions::Exponential { mean } => Exp::new(1.0 / mean.as_secs_f64())
+                .expect(CHECKED)
+                .sample(rng),
+            Sessions::Weibull { scale, shape } => Weibull::new(scale.as_secs_f64(), shape)
+                .expect(CHECKED)
+                .sample(rng),
+            Sessions::Pareto { scale, shape } => Pareto::new(scale.as_secs_f64(), shape)
+                .expect(CHECKED)
+                .sample(rng),
+        };
+
+        Time::from_secs_f64(secs)
+    }
+}
+
+/// Nodes alternating between sessions and downtimes.
+///
+/// Every node present when churn starts, and every node which later comes
+/// back, stays up for a time drawn from `session`, then leaves and comes back
+/// as a fresh node after a time drawn from `downtime`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChurnModel {
+    pub session: SessionDistribution,
+    pub downtime: SessionDistribution,
+    /// Probability a departure is graceful rather than a crash.
+    pub graceful: f64,
+}
+
+/// A node coming up or going down in a [`ChurnTrace`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub time: Time,
+    /// Identifies the node across its sessions.
+    pub id: u64,
+    /// `None` when the node comes up.
+    pub departure: Option<Departure>,
+}
+
+/// Recorded churn, replayed as is.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChurnTrace {
+    entries: Vec<TraceEntry>,
+}
+
+impl ChurnTrace {
+    pub fn new(mut entries: Vec<TraceEntry>) -> Self {
+        entries.sort_by_key(|entry| entry.time);
+        Self { entries }
+    }
+
+    /// Reads a trace with one `<seconds>,<id>,<up|down>[,<graceful|crash>]`
+    /// entry per line. Departures are crashes unless stated otherwise, and
+    /// lines starting with `#` are ignored.
+    pub fn from_csv(reader: impl BufRead) -> Result<Self, ChurnError> {
+        let mut entries = Vec::new();
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid = || ChurnError::LineInvalid { line: i + 1 };
+            let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+
+            let (time, id, state, departure) = match fields[..] {
+                [time, id, state] => (time, id, state, None),
+                [time, id, state, departure] => (time, id, state, Some(departure)),
+                _ => return Err(invalid()),
+            };
+
+            let time = time
+                .parse::<f64>()
+                .ok()
+                .filter(|secs| *secs >= 0.0)
+                .ok_or_else(invalid)?;
+            let id = id.parse::<u64>().map_err(|_| invalid())?;
+            let departure = match (state, departure) {
+                ("up", None) => None,
+                ("down", None | Some("crash")) => Some(Departure::Crash),
+                ("down", Some("graceful")) => Some(Departure::Graceful),
+                _ => return Err(invalid()),
+            };
+
+            entries.push(TraceEntry {
+                time: Time::from_secs_f64(time),
+                id,
+                departure,
+            });
+        }
+
+        Ok(Self::new(entries))
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ChurnError> {
+        Self::from_csv(BufReader::new(File::open(path)?))
+    }
+
+    /// Entries, in time order.
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+}
+
+/// Number of joins and departures caused by churn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChurnStats {
+    pub joins: u64,
+    pub graceful: u64,
+    pub crashes: u64,
+}
+
+impl Encode for Departure {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Departure::Graceful => write_tag(out, 0),
+            Departure::Crash => write_tag(out, 1),
+        }
+    }
+}
+
+impl Decode for Departure {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        match input.read_u8()? {
+            0 => Ok(Departure::Graceful),
+            1 => Ok(Departure::Crash),
+            tag => Err(DecodeError::TagInvalid {
+                kind: "departure",
+                tag,
+            }),
+        }
+    }
+}
+
+impl Encode for SessionDistribution {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self.0 {
+            Sessions::Exponential { mean } => {
+                write_tag(out, 0);
+                mean.encode(out);
+            }
+            Sessions::Weibull { scale, shape } => {
+                write_tag(out, 1);
+                scale.encode(out);
+                shape.encode(out);
+            }
+            Sessions::Pareto { scale, shape } => {
+                write_tag(out, 2);
+                scale.encode(out);
+                shape.encode(out);
+            }
+        }
+    }
+}
+
+impl Decode for SessionDistribution {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        let sessions = match input.read_u8()? {
+            0 => Sessions::Exponential {
+                mean: Time::decode(input)?,
+            },
+            1 => Sessions::Weibull {
+                scale: Time::decode(input)?,
+                shape: f64::decode(input)?,
+            },
+            2 => Sessions::Pareto {
+                scale: Time::decode(input)?,
+                shape: f64::decode(input)?,
+            },
+            tag => {
+                return Err(DecodeError::TagInvalid {
+                    kind: "session distribution",
+                    tag,
+                })
+            }
+        };
+
+        Self::checked(sessions).map_err(|_| DecodeError::ValueInvalid {
+            kind: "session distribution",
+        })
+    }
+}
+
+impl Encode for ChurnModel {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.session.encode(out);
+        self.downtime.encode(out);
+        self.graceful.encode(out);
+    }
+}
+
+impl Decode for ChurnModel {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            session: SessionDistribution::decode(input)?,
+            downtime: SessionDistribution::decode(input)?,
+            graceful: f64::decode(input)?,
+        })
+    }
+}
+
+impl Encode for ChurnStats {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.joins.encode(out);
+        self.graceful.encode(out);
+        self.crashes.encode(out);
+    }
+}
+
+impl Decode for ChurnStats {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            joins: u64::decode(input)?,
+            graceful: u64::decode(input)?,
+            crashes: u64::decode(input)?,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        codec::{Decode, DecodeError, Encode},
+        simulator::{SimRng, Time},
+    };
+
+    use super::{ChurnError, ChurnModel, ChurnTrace, Departure, SessionDistribution};
+
+    fn mean(distribution: SessionDistribution) -> f64 {
+        let mut rng = SimRng::new(0);
+        let n = 10_000;
+        (0..n)
+            .map(|_| distribution.sample(&mut rng).as_secs_f64())
+            .sum::<f64>()
+            / n as f64
+    }
+
+    #[test]
+    fn distributions() {
+        let exponential = SessionDistribution::exponential(Time::from_secs(60)).unwrap();
+        let mean_exponential = mean(exponential);
+        assert!((55.0..65.0).contains(&mean_exponential));
+
+        // Mean of a Pareto distribution is `scale * shape / (shape - 1)`
+        let pareto = SessionDistribution::pareto(Time::from_secs(10), 3.0).unwrap();
+        let mean_pareto = mean(pareto);
+        assert!((14.0..16.0).contains(&mean_pareto));
+
+        let weibull = SessionDistribution::weibull(Time::from_secs(60), 0.5).unwrap();
+        let mut rng = SimRng::new(0);
+        assert!((0..100).all(|_| weibull.sample(&mut rng) >= Time::ZERO));
+
+        assert!(SessionDistribution::weibull(Time::from_secs(60), -1.0).is_err());
+    }
+
+    #[test]
+    fn encoding() {
+        let model = ChurnModel {
+            session: SessionDistribution::weibull(Time::from_secs(600), 0.5).unwrap(),
+            downtime: SessionDistribution::pareto(Time::from_secs(30), 2.0).unwrap(),
+            graceful: 0.25,
+        };
+        assert_eq!(ChurnModel::from_bytes(&model.to_bytes()), Ok(model));
+
+        // Tag and shape of a Weibull distribution with a negative shape
+        let mut invalid = vec![1];
+        Time::from_secs(60).encode(&mut invalid);
+        (-1.0f64).encode(&mut invalid);
+        assert_eq!(
+            SessionDistribution::from_bytes(&invalid),
+            Err(DecodeError::ValueInvalid {
+                kind: "session distribution"
+            })
+        );
+    }
+
+    #[test]
+    fn trace_from_csv() {
+        let csv = "# time,id,state\n5,1,down,graceful\n0,1,up\n2.5, 2, up\n7,2,down\n";
+        let trace = ChurnTrace::from_csv(csv.as_bytes()).unwrap();
+        let entries = trace.entries();
+
+        assert_eq!(entries.len(), 4);
+        assert_eq!((entries[0].time, entries[0].id), (Time::ZERO, 1));
+        assert_eq!(entries[1].time, Time::from_millis(2500));
+        assert_eq!(entries[2].departure, Some(Departure::Graceful));
+        assert_eq!(entries[3].departure, Some(Departure::Crash));
+
+        assert!(matches!(
+            ChurnTrace::from_csv("0,1,sideways\n".as_bytes()),
+            Err(ChurnError::LineInvalid { line: 1 })
+        ));
+    }
+}
@@ -0,0 +1,281 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::{
+    codec::{write_tag, Decode, DecodeError, Decoder, Encode},
+    network::Address,
+    node::{Contact, Operation, Timer},
+};
+
+use super::{Departure, Partition, Time};
+
+/// Something which happens in the simulation at a given virtual time.
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// `frame`, an RPC encoded by [`Rpc::to_frame`](crate::node::Rpc::to_frame),
+    /// reaches `to`.
+    Deliver { to: Contact, frame: Vec<u8> },
+    /// A timer set by the node at `address` fires.
+    Timer { address: Address, timer: Timer },
+    /// The node at `address` starts `operation`.
+    Operation {
+        address: Address,
+        operation: Operation,
+    },
+    /// A new node joins the network through `peers`, see
+    /// [`Operation::Join`]. With no `peers`, it joins through one random node
+    /// already in the network.
+    Join { name: String, peers: Vec<Contact> },
+    /// The node at `address` leaves the network.
+    Leave {
+        address: Address,
+        departure: Departure,
+    },
+    /// The node identified by `slot` in a churn model or trace comes up as a
+    /// fresh node, knowing of one random node already in the network.
+    Up { slot: u64 },
+    /// The node identified by `slot` in a churn model or trace goes down.
+    Down { slot: u64, departure: Departure },
+    /// Nodes can only reach nodes in the same group of `partition`.
+    Partition { partition: Partition },
+    /// Ends the current partition.
+    Heal,
+    /// Records how far the network has diverged across the last partition.
+    Measure,
+}
+
+#[derive(Debug)]
+struct Scheduled {
+    time: Time,
+    seq: u64,
+    event: Event,
+}
+
+impl PartialEq for Scheduled {
+    fn eq(&self, other: &Self) -> bool {
+        (self.time, self.seq) == (other.time, other.seq)
+    }
+}
+
+impl Eq for Scheduled {}
+
+impl PartialOrd for Scheduled {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scheduled {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.time, self.seq).cmp(&(other.time, other.seq))
+    }
+}
+
+/// Priority queue of events ordered by time. Events scheduled for the same
+/// time are popped in the order they were pushed, which keeps runs
+/// deterministic.
+#[derive(Debug, Default)]
+pub struct EventQueue {
+    heap: BinaryHeap<Reverse<Scheduled>>,
+    seq: u64,
+}
+
+impl EventQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, time: Time, event: Event) {
+        self.heap.push(Reverse(Scheduled {
+            time,
+            seq: self.seq,
+            event,
+        }));
+        self.seq += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<(Time, Event)> {
+        self.heap
+            .pop()
+            .map(|Reverse(scheduled)| (scheduled.time, scheduled.event))
+    }
+
+    /// Time of the next event.
+    pub fn peek(&self) -> Option<Time> {
+        self.heap.peek().map(|Reverse(scheduled)| scheduled.time)
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+}
+
+impl Encode for Event {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Event::Deliver { to, frame } => {
+                write_tag(out, 0);
+                to.encode(out);
+                frame.encode(out);
+            }
+            Event::Timer { address, timer } => {
+                write_tag(out, 1);
+                address.encode(out);
+                timer.encode(out);
+            }
+            Event::Operation { address, operation } => {
+                write_tag(out, 2);
+                address.encode(out);
+                operation.encode(out);
+            }
+            Event::Join { name, peers } => {
+                write_tag(out, 3);
+                name.encode(out);
+                peers.encode(out);
+            }
+            Event::Leave { address, departure } => {
+                write_tag(out, 4);
+                address.encode(out);
+                departure.encode(out);
+            }
+            Event::Up { slot } => {
+                write_tag(out, 5);
+                slot.encode(out);
+            }
+            Event::Down { slot, departure } => {
+                write_tag(out, 6);
+                slot.encode(out);
+                departure.encode(out);
+            }
+            Event::Partition { partition } => {
+                write_tag(out, 7);
+                partition.encode(out);
+            }
+            Event::Heal => write_tag(out, 8),
+            Event::Measure => write_tag(out, 9),
+        }
+    }
+}
+
+impl Decode for Event {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(match input.read_u8()? {
+            0 => Event::Deliver {
+                to: Contact::decode(input)?,
+                frame: Vec::decode(input)?,
+            },
+            1 => Event::Timer {
+                address: Address::decode(input)?,
+                timer: Timer::decode(input)?,
+            },
+            2 => Event::Operation {
+                address: Address::decode(input)?,
+                operation: Operation::decode(input)?,
+            },
+            3 => Event::Join {
+                name: String::decode(input)?,
+                peers: Vec::decode(input)?,
+            },
+            4 => Event::Leave {
+                address: Address::decode(input)?,
+                departure: Departure::decode(input)?,
+            },
+            5 => Event::Up {
+                slot: u64::decode(input)?,
+            },
+            6 => Event::Down {
+                slot: u64::decode(input)?,
+                departure: Departure::decode(input)?,
+            },
+            7 => Event::Partition {
+                partition: Partition::decode(input)?,
+            },
+            8 => Event::Heal,
+            9 => Event::Measure,
+            tag => return Err(DecodeError::TagInvalid { kind: "event", tag }),
+        })
+    }
+}
+
+/// Encodes pending events in the order they will be popped, along with
+/// their sequence numbers so that ties are still broken the same way.
+impl Encode for EventQueue {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let mut scheduled = self.heap.iter().map(|Reverse(s)| s).collect::<Vec<_>>();
+        scheduled.sort();
+
+        scheduled.len().encode(out);
+        for scheduled in scheduled {
+            scheduled.time.encode(out);
+            scheduled.seq.encode(out);
+            scheduled.event.encode(out);
+        }
+        self.seq.encode(out);
+    }
+}
+
+impl Decode for EventQueue {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        let len = input.read_len()?;
+        let mut heap = BinaryHeap::with_capacity(len);
+
+        for _ in 0..len {
+            heap.push(Reverse(Scheduled {
+                time: Time::decode(input)?,
+                seq: u64::decode(input)?,
+                event: Event::decode(input)?,
+            }));
+        }
+
+        Ok(Self {
+            heap,
+            seq: u64::decode(input)?,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        network::Address,
+        simulator::{Departure, Time},
+    };
+
+    use super::{Event, EventQueue};
+
+    fn leave(i: u32) -> Event {
+        Event::Leave {
+            address: Address::new(i),
+            departure: Departure::Crash,
+        }
+    }
+
+    fn address(event: Event) -> u32 {
+        match event {
+            Event::Leave { address, .. } => address.index() as u32,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn ordered_by_time_then_insertion() {
+        let mut queue = EventQueue::new();
+
+        queue.push(Time::from_secs(2), leave(0));
+        queue.push(Time::from_secs(1), leave(1));
+        queue.push(Time::from_secs(2), leave(2));
+        queue.push(Time::from_secs(1), leave(3));
+
+        assert_eq!(queue.peek(), Some(Time::from_secs(1)));
+
+        let order = std::iter::from_fn(|| queue.pop())
+            .map(|(_, event)| address(event))
+            .collect::<Vec<_>>();
+
+        assert_eq!(order, [1, 3, 0, 2]);
+        assert!(queue.is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use rand::Rng;
+use rand_distr::{Distribution, LogNormal};
+
+use crate::network::Address;
+
+use super::{SimRng, Time};
+
+/// How long a message takes to travel from one node to another.
+///
+/// Latency is drawn for every message, using the simulation [`SimRng`] so
+/// that runs stay reproducible.
+pub trait LatencyModel {
+    fn latency(&self, from: Address, to: Address, rng: &mut SimRng) -> Time;
+}
+
+#[derive(Debug)]
+pub enum LatencyError {
+    Io(std::io::Error),
+    /// A matrix entry is not a number.
+    ValueInvalid {
+        line: usize,
+        value: String,
+    },
+    /// A matrix row does not have as many entries as there are rows.
+    MatrixNotSquare {
+        rows: usize,
+        row: usize,
+        len: usize,
+    },
+    MatrixEmpty,
+    /// Distribution parameters are out of range.
+    ParameterInvalid,
+}
+
+impl std::fmt::Display for LatencyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LatencyError::Io(err) => write!(f, "Failed to read latency matrix: {err}"),
+            LatencyError::ValueInvalid { line, value } => {
+                write!(f, "Invalid latency {value:?} on line {line}")
+            }
+            LatencyError::MatrixNotSquare { rows, row, len } => write!(
+                f,
+                "Latency matrix is not square: row {row} has {len} entries, expected {rows}"
+            ),
+            LatencyError::MatrixEmpty => write!(f, "Latency matrix is empty"),
+            LatencyError::ParameterInvalid => write!(f, "Invalid latency distribution parameter"),
+        }
+    }
+}
+
+impl std::error::Error for LatencyError {}
+
+impl From<std::io::Error> for LatencyError {
+    fn from(err: std::io::Error) -> Self {
+        LatencyError::Io(err)
+    }
+}
+
+/// Every message takes the same time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConstantLatency(pub Time);
+
+impl Default for ConstantLatency {
+    fn default() -> Self {
+        Self(Time::from_millis(50))
+    }
+}
+
+impl LatencyModel for ConstantLatency {
+    fn latency(&self, _from: Address, _to: Address, _rng: &mut SimRng) -> Time {
+        self.0
+    }
+}
+
+/// Latency drawn uniformly in `[min, max]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UniformLatency {
+    pub min: Time,
+    pub max: Time,
+}
+
+impl LatencyModel for UniformLatency {
+    fn latency(&self, _from: Address, _to: Address, rng: &mut SimRng) -> Time {
+        let (min, max) = (self.min.as_micros(), self.max.as_micros());
+        Time::from_micros(rng.gen_range(min.min(max)..=max.max(min)))
+    }
+}
+
+/// Log-normally distributed latency. Internet round-trip times are heavy
+/// tailed: most messages arrive close to the median, but a few take many
+/// times longer.
+#[derive(Clone, Copy, Debug)]
+pub struct LogNormalLatency {
+    distribution: LogNormal<f64>,
+}
+
+impl LogNormalLatency {
+    /// Latency with the given `median`, and `sigma` the standard deviation
+    /// of its logarithm.
+    pub fn new(median: Time, sigma: f64) -> Result<Self, LatencyError> {
+        if !(sigma.is_finite() && sigma >= 0.0) {
+            return Err(LatencyError::ParameterInvalid);
+        }
+
+        let mu = (median.as_micros().max(1) as f64).ln();
+        let distribution = LogNormal::new(mu, sigma).map_err(|_| LatencyError::ParameterInvalid)?;
+
+        Ok(Self { distribution })
+    }
+}
+
+impl LatencyModel for LogNormalLatency {
+    fn latency(&self, _from: Address, _to: Address, rng: &mut SimRng) -> Time {
+        Time::from_micros(self.distribution.sample(rng) as u64)
+    }
+}
+
+/// Measured latency between every pair of hosts.
+///
+/// Nodes are mapped onto hosts by address, wrapping around if there are more
+/// nodes than hosts. Pairs without a measurement fall back to the mean of
+/// all known latencies.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LatencyMatrix {
+    hosts: usize,
+    delays: Vec<Option<Time>>,
+    fallback: Time,
+}
+
+impl LatencyMatrix {
+    /// Builds a matrix from its rows, `delays[i][j]` being the one-way latency
+    /// from host `i` to host `j`.
+    pub fn new(delays: Vec<Vec<Option<Time>>>) -> Result<Self, LatencyError> {
+        let hosts = delays.len();
+
+        if hosts == 0 {
+            return Err(LatencyError::MatrixEmpty);
+        }
+
+        if let Some((i, row)) = delays
+            .iter()
+            .enumerate()
+            .find(|(_, row)| row.len() != hosts)
+        {
+            return Err(LatencyError::MatrixNotSquare {
+                rows: hosts,
+                row: i + 1,
+                len: row.len(),
+            });
+        }
+
+        let delays = delays.into_iter().flatten().collect::<Vec<_>>();
+
+        // The diagonal is a host's latency to itself, which would drag the
+        // mean down
+        let (sum, count) = delays
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| i / hosts != i % hosts)
+            .filter_map(|(_, delay)| *delay)
+            .fold((0, 0), |(sum, count), delay| {
+                (sum + delay.as_micros(), count + 1)
+            });
+        let fallback = Time::from_micros(sum.checked_div(count).unwrap_or_default());
+
+        Ok(Self {
+            hosts,
+            delays,
+            fallback,
+        })
+    }
+
+    /// Reads a square matrix of round-trip times in microseconds, one row
+    /// per line with entries separated by commas or whitespace, as in the
+    /// King dataset. One-way latency is taken to be half the round-trip
+    /// time. Negative entries mark missing measurements, and lines starting
+    /// with `#` are ignored.
+    pub fn from_csv(reader: impl BufRead) -> Result<Self, LatencyError> {
+        let mut rows = Vec::new();
+
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let row = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|value| !value.is_empty())
+                .map(|value| match value.parse::<f64>() {
+                    Ok(rtt) if rtt >= 0.0 => Ok(Some(Time::from_micros((rtt / 2.0) as u64))),
+                    Ok(_) => Ok(None),
+                    Err(_) => Err(LatencyError::ValueInvalid {
+                        line: i + 1,
+                        value: value.to_string(),
+                    }),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            rows.push(row);
+        }
+
+        Self::new(rows)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, LatencyError> {
+        Self::from_csv(BufReader::new(File::open(path)?))
+    }
+
+    pub fn hosts(&self) -> usize {
+        self.hosts
+    }
+}
+
+impl LatencyModel for LatencyMatrix {
+    fn latency(&self, from: Address, to: Address, _rng: &mut SimRng) -> Time {
+        let (i, j) = (from.index() % self.hosts, to.index() % self.hosts);
+        self.delays[i * self.hosts + j].unwrap_or(self.fallback)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{network::Address, simulator::SimRng};
+
+    use super::{
+        ConstantLatency, LatencyError, LatencyMatrix, LatencyModel, LogNormalLatency, Time,
+        UniformLatency,
+    };
+
+    fn draw(model: &impl LatencyModel, n: u32) -> Vec<Time> {
+        let mut rng = SimRng::new(0);
+        (0..n)
+            .map(|i| model.latency(Address::new(i), Address::new(i + 1), &mut rng))
+            .collect()
+    }
+
+    #[test]
+    fn constant_and_uniform() {
+        let constant = ConstantLatency(Time::from_millis(10));
+        assert!(draw(&constant, 10)
+            .iter()
+            .all(|t| *t == Time::from_millis(10)));
+
+        let uniform = UniformLatency {
+            min: Time::from_millis(10),
+            max: Time::from_millis(20),
+        };
+        let samples = draw(&uniform, 1000);
+        assert!(samples
+            .iter()
+            .all(|t| (Time::from_millis(10)..=Time::from_millis(20)).contains(t)));
+        assert_ne!(samples.iter().min(), samples.iter().max());
+    }
+
+    #[test]
+    fn log_normal_median() {
+        let model = LogNormalLatency::new(Time::from_millis(80), 0.5).unwrap();
+        let mut samples = draw(&model, 10_001);
+        samples.sort();
+
+        let median = samples[samples.len() / 2].as_millis();
+        assert!((75..=85).contains(&median), "median was {median}ms");
+        assert!(LogNormalLatency::new(Time::from_millis(80), -1.0).is_err());
+    }
+
+    #[test]
+    fn matrix_from_csv() {
+        let csv = "# King RTTs in microseconds\n0, 20000, -1\n20000 0 40000\n\n10000,40000,0\n";
+        let matrix = LatencyMatrix::from_csv(csv.as_bytes()).unwrap();
+        let mut rng = SimRng::new(0);
+        let latency =
+            |from, to, rng: &mut SimRng| matrix.latency(Address::new(from), Address::new(to), rng);
+
+        assert_eq!(matrix.hosts(), 3);
+        assert_eq!(latency(0, 1, &mut rng), Time::from_millis(10));
+        assert_eq!(latency(2, 0, &mut rng), Time::from_millis(5));
+        // Missing entry falls back to the mean, and addresses wrap around
+        assert_eq!(latency(0, 2, &mut rng), Time::from_millis(13));
+        assert_eq!(latency(4, 3, &mut rng), Time::from_millis(10));
+    }
+
+    #[test]
+    fn matrix_invalid() {
+        assert!(matches!(
+            LatencyMatrix::from_csv("0,1\n1\n".as_bytes()),
+            Err(LatencyError::MatrixNotSquare { row: 2, len: 1, .. })
+        ));
+        assert!(matches!(
+            LatencyMatrix::from_csv("0,x\n1,0\n".as_bytes()),
+            Err(LatencyError::ValueInvalid { line: 1, .. })
+        ));
+        assert!(matches!(
+            LatencyMatrix::from_csv("".as_bytes()),
+            Err(LatencyError::MatrixEmpty)
+        ));
+    }
+}
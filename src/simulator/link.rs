@@ -0,0 +1,274 @@
+use indexmap::IndexMap;
+use rand::Rng;
+
+use crate::{
+    codec::{Decode, DecodeError, Decoder, Encode},
+    network::Address,
+};
+
+use super::{SimRng, Time};
+
+/// Faults injected on messages travelling over a link. Every probability is
+/// in `[0, 1]` and applies independently to each message.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinkFaults {
+    /// Probability a message is lost.
+    pub drop: f64,
+    /// Probability a message is delivered twice.
+    pub duplicate: f64,
+    /// Probability a message is held back by `delay_by`, on top of its
+    /// latency.
+    pub delay: f64,
+    pub delay_by: Time,
+    /// Probability a message is held back by a random time of up to
+    /// `reorder_window`, letting messages sent after it overtake it.
+    pub reorder: f64,
+    pub reorder_window: Time,
+}
+
+impl LinkFaults {
+    /// Only drops messages, with probability `drop`.
+    pub fn lossy(drop: f64) -> Self {
+        Self {
+            drop,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_reliable(&self) -> bool {
+        self.drop <= 0.0 && self.duplicate <= 0.0 && self.delay <= 0.0 && self.reorder <= 0.0
+    }
+}
+
+/// Number of messages each fault was applied to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    pub sent: u64,
+    pub dropped: u64,
+    pub duplicated: u64,
+    pub delayed: u64,
+    pub reordered: u64,
+}
+
+/// Decides the fate of every message sent over the network.
+///
+/// Faults default to `global`, and can be overridden for individual
+/// directed links. Reliable links never draw from the [`SimRng`], so adding
+/// a link model with no faults does not change the outcome of a run.
+#[derive(Clone, Debug, Default)]
+pub struct LinkModel {
+    global: LinkFaults,
+    links: IndexMap<(Address, Address), LinkFaults>,
+    stats: LinkStats,
+}
+
+impl LinkModel {
+    pub fn new(global: LinkFaults) -> Self {
+        Self {
+            global,
+            ..Default::default()
+        }
+    }
+
+    /// Overrides the faults of messages sent from `from` to `to`.
+    pub fn with_link(mut self, from: Address, to: Address, faults: LinkFaults) -> Self {
+        self.set_link(from, to, faults);
+        self
+    }
+
+    pub fn set_link(&mut self, from: Address, to: Address, faults: LinkFaults) {
+        self.links.insert((from, to), faults);
+    }
+
+    /// Goes back to the global faults for messages sent from `from` to `to`.
+    pub fn reset_link(&mut self, from: Address, to: Address) {
+        self.links.swap_remove(&(from, to));
+    }
+
+    pub fn faults(&self, from: Address, to: Address) -> &LinkFaults {
+        self.links.get(&(from, to)).unwrap_or(&self.global)
+    }
+
+    pub fn stats(&self) -> &LinkStats {
+        &self.stats
+    }
+
+    /// Delays after which copies of a message sent from `from` to `to` with
+    /// the given `latency` arrive: none if it is dropped, two if it is
+    /// duplicated.
+    pub fn deliveries(
+        &mut self,
+        from: Address,
+        to: Address,
+        latency: Time,
+        rng: &mut SimRng,
+    ) -> Vec<Time> {
+        let faults = *self.faults(from, to);
+        self.stats.sent += 1;
+
+        if faults.is_reliable() {
+            return vec![latency];
+        }
+
+        if rng.gen_bool(faults.drop.clamp(0.0, 1.0)) {
+            self.stats.dropped += 1;
+            return Vec::new();
+        }
+
+        let copies = if rng.gen_bool(faults.duplicate.clamp(0.0, 1.0)) {
+            self.stats.duplicated += 1;
+            2
+        } else {
+            1
+        };
+
+        (0..copies)
+            .map(|_| {
+                let mut delay = latency;
+
+                if rng.gen_bool(faults.delay.clamp(0.0, 1.0)) {
+                    self.stats.delayed += 1;
+                    delay += faults.delay_by;
+                }
+
+                if rng.gen_bool(faults.reorder.clamp(0.0, 1.0)) {
+                    self.stats.reordered += 1;
+                    let window = faults.reorder_window.as_micros();
+                    delay += Time::from_micros(rng.gen_range(0..=window));
+                }
+
+                delay
+            })
+            .collect()
+    }
+}
+
+impl Encode for LinkFaults {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.drop.encode(out);
+        self.duplicate.encode(out);
+        self.delay.encode(out);
+        self.delay_by.encode(out);
+        self.reorder.encode(out);
+        self.reorder_window.encode(out);
+    }
+}
+
+impl Decode for LinkFaults {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            drop: f64::decode(input)?,
+            duplicate: f64::decode(input)?,
+            delay: f64::decode(input)?,
+            delay_by: Time::decode(input)?,
+            reorder: f64::decode(input)?,
+            reorder_window: Time::decode(input)?,
+        })
+    }
+}
+
+impl Encode for LinkStats {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.sent.encode(out);
+        self.dropped.encode(out);
+        self.duplicated.encode(out);
+        self.delayed.encode(out);
+        self.reordered.encode(out);
+    }
+}
+
+impl Decode for LinkStats {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            sent: u64::decode(input)?,
+            dropped: u64::decode(input)?,
+            duplicated: u64::decode(input)?,
+            delayed: u64::decode(input)?,
+            reordered: u64::decode(input)?,
+        })
+    }
+}
+
+impl Encode for LinkModel {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.global.encode(out);
+        self.links.encode(out);
+        self.stats.encode(out);
+    }
+}
+
+impl Decode for LinkModel {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            global: LinkFaults::decode(input)?,
+            links: IndexMap::decode(input)?,
+            stats: LinkStats::decode(input)?,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{network::Address, simulator::SimRng};
+
+    use super::{LinkFaults, LinkModel, Time};
+
+    #[test]
+    fn reliable_by_default() {
+        let mut model = LinkModel::default();
+        let mut rng = SimRng::new(0);
+        let latency = Time::from_millis(10);
+
+        for i in 0..100 {
+            let deliveries = model.deliveries(Address::new(i), Address::new(0), latency, &mut rng);
+            assert_eq!(deliveries, [latency]);
+        }
+
+        assert_eq!(model.stats().sent, 100);
+        assert_eq!(rng, SimRng::new(0));
+    }
+
+    #[test]
+    fn faults_are_applied() {
+        let faults = LinkFaults {
+            drop: 0.2,
+            duplicate: 0.2,
+            delay: 0.2,
+            delay_by: Time::from_secs(1),
+            reorder: 0.2,
+            reorder_window: Time::from_millis(100),
+        };
+        let mut model = LinkModel::new(faults);
+        let mut rng = SimRng::new(0);
+        let latency = Time::from_millis(10);
+
+        let deliveries = (0..1000)
+            .map(|_| model.deliveries(Address::new(0), Address::new(1), latency, &mut rng))
+            .collect::<Vec<_>>();
+
+        let stats = *model.stats();
+        let dropped = deliveries.iter().filter(|d| d.is_empty()).count() as u64;
+        let duplicated = deliveries.iter().filter(|d| d.len() == 2).count() as u64;
+
+        assert_eq!(stats.dropped, dropped);
+        assert_eq!(stats.duplicated, duplicated);
+        assert!((150..250).contains(&dropped), "dropped {dropped}");
+        assert!(stats.delayed > 0 && stats.reordered > 0);
+        assert!(deliveries.iter().flatten().all(|d| *d >= latency));
+    }
+
+    #[test]
+    fn per_link_override() {
+        let (a, b) = (Address::new(0), Address::new(1));
+        let mut model =
+            LinkModel::new(LinkFaults::lossy(1.0)).with_link(a, b, LinkFaults::default());
+        let mut rng = SimRng::new(0);
+        let latency = Time::from_millis(10);
+
+        assert_eq!(model.deliveries(a, b, latency, &mut rng), [latency]);
+        assert!(model.deliveries(b, a, latency, &mut rng).is_empty());
+
+        model.reset_link(a, b);
+        assert!(model.deliveries(a, b, latency, &mut rng).is_empty());
+    }
+}
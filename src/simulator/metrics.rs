@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use crate::{network::Address, node::OperationResult};
+
+use super::Time;
+
+/// Numbers recorded for one completed operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OperationRecord {
+    pub address: Address,
+    /// See [`Operation::kind`](crate::node::Operation::kind).
+    pub kind: &'static str,
+    pub hops: usize,
+    /// Requests sent on behalf of the operation.
+    pub rpcs: usize,
+    /// Contacts which failed to respond, as a [`ConnectionStep::Failed`](crate::node::ConnectionStep::Failed).
+    pub failed: usize,
+    /// Virtual time from start to completion.
+    pub latency: Time,
+}
+
+impl From<&OperationResult> for OperationRecord {
+    fn from(result: &OperationResult) -> Self {
+        Self {
+            address: result.address,
+            kind: result.operation.kind(),
+            hops: result.lookup.hops,
+            rpcs: result.messages,
+            failed: result.lookup.failed.len(),
+            latency: result.finished - result.started,
+        }
+    }
+}
+
+/// Messages and bytes a node sent and received.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Traffic {
+    pub sent: u64,
+    pub received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+/// Samples of a quantity, from which percentiles are computed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Histogram {
+    samples: Vec<u64>,
+    sorted: bool,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, value: u64) {
+        self.samples.push(value);
+        self.sorted = false;
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Smallest sample which at least `p` percent of samples do not exceed.
+    pub fn percentile(&mut self, p: f64) -> Option<u64> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        self.sort();
+        let rank = (p.clamp(0.0, 100.0) / 100.0 * self.samples.len() as f64).ceil() as usize;
+        Some(self.samples[rank.max(1) - 1])
+    }
+
+    /// Number of samples in each bucket of `width`, keyed by the lower
+    /// bound of the bucket. Empty buckets are left out.
+    pub fn buckets(&self, width: u64) -> BTreeMap<u64, usize> {
+        let width = width.max(1);
+        let mut buckets = BTreeMap::new();
+
+        for sample in self.samples.iter() {
+            *buckets.entry(sample / width * width).or_default() += 1;
+        }
+
+        buckets
+    }
+
+    pub fn summary(&mut self) -> Option<Summary> {
+        let count = self.samples.len();
+        let sum = self
+            .samples
+            .iter()
+            .map(|sample| *sample as f64)
+            .sum::<f64>();
+
+        Some(Summary {
+            count,
+            min: self.percentile(0.0)?,
+            mean: sum / count as f64,
+            p50: self.percentile(50.0)?,
+            p90: self.percentile(90.0)?,
+            p99: self.percentile(99.0)?,
+            max: self.percentile(100.0)?,
+        })
+    }
+
+    fn sort(&mut self) {
+        if !self.sorted {
+            self.samples.sort_unstable();
+            self.sorted = true;
+        }
+    }
+}
+
+impl FromIterator<u64> for Histogram {
+    fn from_iter<T: IntoIterator<Item = u64>>(iter: T) -> Self {
+        Self {
+            samples: iter.into_iter().collect(),
+            sorted: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Summary {
+    pub count: usize,
+    pub min: u64,
+    pub mean: f64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "min {} mean {:.1} p50 {} p90 {} p99 {} max {}",
+            self.min, self.mean, self.p50, self.p90, self.p99, self.max
+        )
+    }
+}
+
+/// Metrics of one kind of operation.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct OperationReport {
+    pub count: usize,
+    pub hops: Summary,
+    /// Number of operations by hop count.
+    pub hops_histogram: BTreeMap<u64, usize>,
+    pub rpcs: Summary,
+    pub failed: Summary,
+    /// In microseconds.
+    pub latency: Summary,
+}
+
+/// Everything [`Metrics`] recorded, summarised at the end of a run.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MetricsReport {
+    /// By [`Operation::kind`](crate::node::Operation::kind).
+    pub operations: BTreeMap<&'static str, OperationReport>,
+    /// Messages sent per node.
+    pub sent: Option<Summary>,
+    /// Bytes sent per node.
+    pub bandwidth: Option<Summary>,
+}
+
+impl std::fmt::Display for MetricsReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (kind, report) in self.operations.iter() {
+            writeln!(f, "{kind} ({} operations)", report.count)?;
+            writeln!(f, "  hops        {}", report.hops)?;
+            writeln!(f, "  rpcs        {}", report.rpcs)?;
+            writeln!(f, "  failed      {}", report.failed)?;
+            writeln!(f, "  latency µs  {}", report.latency)?;
+        }
+        if let Some(sent) = self.sent {
+            writeln!(f, "messages sent per node  {sent}")?;
+        }
+        if let Some(bandwidth) = self.bandwidth {
+            writeln!(f, "bytes sent per node     {bandwidth}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Numbers recorded by the [`Simulator`](super::Simulator) over a run.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    operations: Vec<OperationRecord>,
+    traffic: IndexMap<Address, Traffic>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_operation(&mut self, result: &OperationResult) {
+        self.operations.push(result.into());
+    }
+
+    /// Records a frame of `bytes` bytes sent by `from`.
+    pub fn record_sent(&mut self, from: Address, bytes: usize) {
+        let traffic = self.traffic.entry(from).or_default();
+        traffic.sent += 1;
+        traffic.bytes_sent += bytes as u64;
+    }
+
+    /// Records a frame of `bytes` bytes received by `to`.
+    pub fn record_received(&mut self, to: Address, bytes: usize) {
+        let traffic = self.traffic.entry(to).or_default();
+        traffic.received += 1;
+        traffic.bytes_received += bytes as u64;
+    }
+
+    /// Every completed operation, in completion order.
+    pub fn operations(&self) -> &[OperationRecord] {
+        &self.operations
+    }
+
+    /// Traffic of every node which sent or received anything.
+    pub fn traffic(&self) -> &IndexMap<Address, Traffic> {
+        &self.traffic
+    }
+
+    pub fn report(&self) -> MetricsReport {
+        let mut kinds = BTreeMap::<_, Vec<_>>::new();
+        for record in self.operations.iter() {
+            kinds.entry(record.kind).or_default().push(record);
+        }
+
+        let operations = kinds
+            .into_iter()
+            .filter_map(|(kind, records)| {
+                let histogram = |f: fn(&OperationRecord) -> u64| {
+                    records
+                        .iter()
+                        .map(|record| f(record))
+                        .collect::<Histogram>()
+                };
+                let mut hops = histogram(|record| record.hops as u64);
+
+                let report = OperationReport {
+                    count: records.len(),
+                    hops_histogram: hops.buckets(1),
+                    hops: hops.summary()?,
+                    rpcs: histogram(|record| record.rpcs as u64).summary()?,
+                    failed: histogram(|record| record.failed as u64).summary()?,
+                    latency: histogram(|record| record.latency.as_micros()).summary()?,
+                };
+
+                Some((kind, report))
+            })
+            .collect();
+
+        let per_node = |f: fn(&Traffic) -> u64| {
+            self.traffic
+                .values()
+                .map(f)
+                .collect::<Histogram>()
+                .summary()
+        };
+
+        MetricsReport {
+            operations,
+            sent: per_node(|traffic| traffic.sent),
+            bandwidth: per_node(|traffic| traffic.bytes_sent),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::Histogram;
+
+    #[test]
+    fn percentiles() {
+        let mut histogram = (1..=100).rev().collect::<Histogram>();
+
+        assert_eq!(histogram.percentile(0.0), Some(1));
+        assert_eq!(histogram.percentile(50.0), Some(50));
+        assert_eq!(histogram.percentile(99.0), Some(99));
+        assert_eq!(histogram.percentile(100.0), Some(100));
+
+        let summary = histogram.summary().unwrap();
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.mean, 50.5);
+        assert_eq!(summary.p90, 90);
+
+        assert!(Histogram::new().summary().is_none());
+    }
+
+    #[test]
+    fn buckets() {
+        let histogram = [1, 2, 2, 9, 10, 25].into_iter().collect::<Histogram>();
+        let buckets = histogram.buckets(10);
+
+        assert_eq!(
+            buckets.into_iter().collect::<Vec<_>>(),
+            [(0, 4), (10, 1), (20, 1)]
+        );
+    }
+}
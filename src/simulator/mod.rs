@@ -0,0 +1,1209 @@
+mod churn;
+mod event;
+mod latency;
+mod link;
+mod metrics;
+mod partition;
+mod rng;
+mod snapshot;
+mod time;
+mod trace;
+
+use indexmap::{IndexMap, IndexSet};
+use rand::Rng;
+
+use crate::{
+    codec::Encode,
+    network::{Address, Network},
+    node::{Action, Contact, Operation, OperationResult, ProtocolConfig, Rpc, HEADER_LEN},
+    primitives::GUID,
+};
+
+pub use churn::{
+    ChurnError, ChurnModel, ChurnStats, ChurnTrace, Departure, SessionDistribution, TraceEntry,
+};
+pub use event::{Event, EventQueue};
+pub use latency::{
+    ConstantLatency, LatencyError, LatencyMatrix, LatencyModel, LogNormalLatency, UniformLatency,
+};
+pub use link::{LinkFaults, LinkModel, LinkStats};
+pub use metrics::{
+    Histogram, Metrics, MetricsReport, OperationRecord, OperationReport, Summary, Traffic,
+};
+pub use partition::{Divergence, Partition};
+pub use rng::SimRng;
+pub use snapshot::SnapshotError;
+pub use time::Time;
+pub use trace::{DropReason, MessageSummary, TraceEvent, Tracer};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SimulatorConfig {
+    pub protocol: ProtocolConfig,
+}
+
+/// Work done by nodes to keep the network healthy, see
+/// [`Operation::is_maintenance`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MaintenanceStats {
+    pub refreshes: u64,
+    pub republishes: u64,
+    /// Requests sent for maintenance.
+    pub messages: u64,
+}
+
+/// Discrete-event simulator driving a [`Network`].
+///
+/// Nodes never call each other directly: every RPC is a message scheduled for
+/// delivery after a delay drawn from the [`LatencyModel`], and possibly lost
+/// or duplicated along the way by the [`LinkModel`]. Every timeout is a timer
+/// event. The
+/// simulator pops events in time order, advancing its virtual clock to each
+/// event as it goes, and carries out the [`Action`]s nodes return.
+pub struct Simulator {
+    config: SimulatorConfig,
+    latency: Box<dyn LatencyModel>,
+    links: LinkModel,
+    network: Network,
+    queue: EventQueue,
+    now: Time,
+    completed: Vec<OperationResult>,
+    /// Current partition, if any.
+    partition: Option<Partition>,
+    /// Last partition the network went through, which divergence is
+    /// measured against.
+    last_partition: Partition,
+    divergence: Vec<(Time, Divergence)>,
+    churn: Option<ChurnModel>,
+    /// Address of the live node of every churn slot.
+    slots: IndexMap<u64, Address>,
+    churn_stats: ChurnStats,
+    /// Keys stored through [`Operation::Store`].
+    stored: IndexSet<GUID>,
+    maintenance: Option<MaintenanceStats>,
+    /// Earliest time a stored value may expire, when nodes are next swept.
+    next_expiry: Time,
+    expired: u64,
+    processed: u64,
+    /// Frames which could not be decoded on receipt.
+    rejected: u64,
+    metrics: Metrics,
+    tracer: Option<Tracer>,
+}
+
+impl Simulator {
+    pub fn new(network: Network, config: SimulatorConfig) -> Self {
+        Self {
+            config,
+            latency: Box::new(ConstantLatency::default()),
+            links: LinkModel::default(),
+            network,
+            queue: EventQueue::new(),
+            now: Time::ZERO,
+            completed: Vec::new(),
+            partition: None,
+            last_partition: Partition::default(),
+            divergence: Vec::new(),
+            churn: None,
+            slots: IndexMap::new(),
+            churn_stats: ChurnStats::default(),
+            stored: IndexSet::new(),
+            maintenance: None,
+            next_expiry: Time::ZERO,
+            expired: 0,
+            processed: 0,
+            rejected: 0,
+            metrics: Metrics::new(),
+            tracer: None,
+        }
+    }
+
+    /// Replaces the default [`ConstantLatency`] model.
+    pub fn with_latency(mut self, latency: impl LatencyModel + 'static) -> Self {
+        self.latency = Box::new(latency);
+        self
+    }
+
+    /// Replaces the default reliable [`LinkModel`].
+    pub fn with_links(mut self, links: LinkModel) -> Self {
+        self.links = links;
+        self
+    }
+
+    pub fn links(&self) -> &LinkModel {
+        &self.links
+    }
+
+    pub fn links_mut(&mut self) -> &mut LinkModel {
+        &mut self.links
+    }
+
+    pub fn now(&self) -> Time {
+        self.now
+    }
+
+    pub fn config(&self) -> &SimulatorConfig {
+        &self.config
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    pub fn network_mut(&mut self) -> &mut Network {
+        &mut self.network
+    }
+
+    /// Has every node, including nodes joining later on, periodically refresh
+    /// its buckets and republish its values.
+    ///
+    /// Maintenance timers keep setting themselves, so the simulation never
+    /// runs out of events: use [`Simulator::run_until`] rather than
+    /// [`Simulator::run`].
+    pub fn maintain(&mut self) {
+        if self.maintenance.is_some() {
+            return;
+        }
+        self.maintenance = Some(MaintenanceStats::default());
+
+        let addresses = self
+            .network
+            .nodes()
+            .map(|node| node.address())
+            .collect::<Vec<_>>();
+
+        for address in addresses {
+            self.start_maintenance(address);
+        }
+    }
+
+    /// Maintenance carried out so far, if enabled.
+    pub fn maintenance_stats(&self) -> Option<&MaintenanceStats> {
+        self.maintenance.as_ref()
+    }
+
+    /// Operations which have completed so far, in completion order.
+    /// Maintenance operations are only accounted for in
+    /// [`Simulator::maintenance_stats`].
+    pub fn completed(&self) -> &[OperationResult] {
+        &self.completed
+    }
+
+    pub fn drain_completed(&mut self) -> impl Iterator<Item = OperationResult> + '_ {
+        self.completed.drain(..)
+    }
+
+    pub fn partition(&self) -> Option<&Partition> {
+        self.partition.as_ref()
+    }
+
+    /// Splits the network according to `partition` from `start` until `end`,
+    /// measuring divergence when the partition starts and when it heals.
+    pub fn partition_between(&mut self, start: Time, end: Time, partition: Partition) {
+        self.schedule(start, Event::Partition { partition });
+        self.schedule(end, Event::Heal);
+    }
+
+    /// How far the network has currently diverged across the last partition.
+    pub fn divergence(&self) -> Divergence {
+        self.last_partition.divergence(&self.network)
+    }
+
+    /// Divergence measured through [`Event::Measure`] and at the start and end
+    /// of partitions, in time order.
+    pub fn divergence_history(&self) -> &[(Time, Divergence)] {
+        &self.divergence
+    }
+
+    /// Subjects every node currently in the network, and every node it is
+    /// later replaced by, to `model`.
+    pub fn churn(&mut self, model: ChurnModel) {
+        self.churn = Some(model);
+
+        let tracked = self.slots.values().copied().collect::<IndexSet<_>>();
+        let untracked = self
+            .network
+            .nodes()
+            .map(|node| node.address())
+            .filter(|address| !tracked.contains(address))
+            .collect::<Vec<_>>();
+
+        let first = self.slots.keys().max().map_or(0, |slot| slot + 1);
+
+        for (slot, address) in (first..).zip(untracked) {
+            self.slots.insert(slot, address);
+            self.schedule_departure(slot);
+        }
+    }
+
+    /// Schedules every entry of `trace`, which is timed from now.
+    pub fn replay(&mut self, trace: &ChurnTrace) {
+        for entry in trace.entries() {
+            let event = match entry.departure {
+                None => Event::Up { slot: entry.id },
+                Some(departure) => Event::Down {
+                    slot: entry.id,
+                    departure,
+                },
+            };
+            self.schedule_in(entry.time, event);
+        }
+    }
+
+    pub fn churn_stats(&self) -> &ChurnStats {
+        &self.churn_stats
+    }
+
+    /// Keys stored through [`Operation::Store`] which no node holds anymore.
+    pub fn lost_keys(&self) -> Vec<GUID> {
+        self.stored
+            .iter()
+            .filter(|key| {
+                !self
+                    .network
+                    .nodes()
+                    .any(|node| node.storage().contains_key(*key))
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Number of stored values which expired so far.
+    pub fn expired(&self) -> u64 {
+        self.expired
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Writes every message, bucket change, store and lookup step from now on
+    /// to `tracer`.
+    pub fn trace(&mut self, tracer: Tracer) {
+        self.config.protocol.trace = true;
+        self.tracer = Some(tracer);
+    }
+
+    /// Stops tracing, and flushes the trace.
+    pub fn finish_trace(&mut self) -> std::io::Result<()> {
+        self.config.protocol.trace = false;
+        self.tracer.take().map_or(Ok(()), Tracer::finish)
+    }
+
+    /// Number of events processed so far.
+    pub fn processed(&self) -> u64 {
+        self.processed
+    }
+
+    /// Number of frames dropped on receipt because they did not decode to an
+    /// RPC, see [`WireError`](crate::node::WireError).
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    /// Number of events waiting to be processed.
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Schedules `event` at `time`. Events cannot be scheduled in the past:
+    /// those are scheduled for the current time instead.
+    pub fn schedule(&mut self, time: Time, event: Event) {
+        self.queue.push(time.max(self.now), event);
+    }
+
+    pub fn schedule_in(&mut self, delay: Time, event: Event) {
+        self.queue.push(self.now + delay, event);
+    }
+
+    /// Adds a new node to the network which only knows of `bootstrap`, and
+    /// has it join the network through it, see [`Operation::Join`].
+    pub fn join(&mut self, name: &str, bootstrap: Contact) -> Contact {
+        self.spawn_and_join(name, &[bootstrap])
+    }
+
+    /// Completed joins, whose duration is the join latency.
+    pub fn joins(&self) -> impl Iterator<Item = &OperationResult> {
+        self.completed
+            .iter()
+            .filter(|result| result.operation == Operation::Join)
+    }
+
+    /// Has the node at `address` start `operation` at the current time.
+    pub fn start(&mut self, address: Address, operation: Operation) {
+        self.schedule_in(Time::ZERO, Event::Operation { address, operation });
+    }
+
+    /// Processes the next event, after purging the values which have expired
+    /// by then. Returns `false` if there were none left.
+    pub fn step(&mut self) -> bool {
+        match self.queue.pop() {
+            Some((time, event)) => {
+                self.now = time;
+                self.expire();
+                self.process(event);
+                self.processed += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Processes every event up to and including `time`, then moves the
+    /// clock to `time`.
+    pub fn run_until(&mut self, time: Time) {
+        while self.queue.peek().is_some_and(|next| next <= time) {
+            self.step();
+        }
+        self.now = self.now.max(time);
+    }
+
+    /// Processes events until there are none left.
+    pub fn run(&mut self) {
+        while self.step() {}
+    }
+
+    fn process(&mut self, event: Event) {
+        let now = self.now;
+        let config = self.config.protocol;
+
+        match event {
+            Event::Deliver { to, frame } => {
+                let rpc = match Rpc::from_frame(&frame) {
+                    Ok(rpc) => rpc,
+                    Err(err) => {
+                        self.rejected += 1;
+                        self.record(to.guid, || TraceEvent::Reject {
+                            bytes: frame.len(),
+                            error: err.to_string(),
+                        });
+                        return;
+                    }
+                };
+
+                let bytes = frame.len();
+                let blocked = self
+                    .partition
+                    .as_ref()
+                    .is_some_and(|p| !p.can_reach(rpc.sender.address, to.address));
+
+                if blocked {
+                    let reason = DropReason::Partition;
+                    self.record(rpc.sender.guid, || {
+                        TraceEvent::drop(&to, reason, &rpc, bytes)
+                    });
+                    return;
+                }
+
+                if self.network.resolve(&to).is_none() {
+                    let reason = DropReason::Unreachable;
+                    self.record(rpc.sender.guid, || {
+                        TraceEvent::drop(&to, reason, &rpc, bytes)
+                    });
+                    return;
+                }
+
+                self.metrics.record_received(to.address, bytes);
+                self.record(to.guid, || TraceEvent::receive(&rpc, bytes));
+
+                let actions = self.network.with_node(to.address, |node, network| {
+                    node.handle(now, rpc, &config, network.rng())
+                });
+
+                if let Some(actions) = actions {
+                    self.apply(to.address, actions);
+                }
+            }
+            Event::Timer { address, timer } => {
+                let actions = self.network.with_node(address, |node, network| {
+                    node.on_timer(now, timer, &config, network.rng())
+                });
+
+                if let Some(actions) = actions {
+                    self.apply(address, actions);
+                }
+            }
+            Event::Operation { address, operation } => {
+                let actions = self.network.with_node(address, |node, network| {
+                    node.start(now, operation, &config, network.rng())
+                });
+
+                if let Some(actions) = actions {
+                    self.apply(address, actions);
+                }
+            }
+            Event::Join { name, mut peers } => {
+                if peers.is_empty() {
+                    peers.extend(self.network.random_contact());
+                }
+                self.spawn_and_join(&name, &peers);
+            }
+            Event::Leave { address, departure } => self.leave(address, departure),
+            Event::Up { slot } => {
+                let peers = self
+                    .network
+                    .random_contact()
+                    .into_iter()
+                    .collect::<Vec<_>>();
+                let contact = self.spawn_and_join(&format!("node-{slot}"), &peers);
+
+                self.slots.insert(slot, contact.address);
+                self.churn_stats.joins += 1;
+                self.schedule_departure(slot);
+            }
+            Event::Down { slot, departure } => {
+                if let Some(address) = self.slots.swap_remove(&slot) {
+                    self.leave(address, departure);
+
+                    if let Some(model) = self.churn {
+                        let downtime = model.downtime.sample(self.network.rng());
+                        self.schedule_in(downtime, Event::Up { slot });
+                    }
+                }
+            }
+            Event::Partition { partition } => {
+                self.last_partition = partition.clone();
+                self.partition = Some(partition);
+                self.measure();
+            }
+            Event::Heal => {
+                self.partition = None;
+                self.measure();
+            }
+            Event::Measure => self.measure(),
+        }
+    }
+
+    fn spawn_and_join(&mut self, name: &str, peers: &[Contact]) -> Contact {
+        let contact = self.network.spawn_with_peers(name, peers);
+        self.record(contact.guid, || TraceEvent::Join);
+        self.start(contact.address, Operation::Join);
+
+        if self.maintenance.is_some() {
+            self.start_maintenance(contact.address);
+        }
+
+        contact
+    }
+
+    fn start_maintenance(&mut self, address: Address) {
+        let config = self.config.protocol;
+
+        if let Some(actions) = self
+            .network
+            .get(address)
+            .map(|node| node.start_maintenance(&config))
+        {
+            self.apply(address, actions);
+        }
+    }
+
+    fn leave(&mut self, address: Address, departure: Departure) {
+        if let Some(node) = self.network.get(address) {
+            self.record(node.guid(), || TraceEvent::leave(departure));
+        }
+
+        if departure == Departure::Graceful {
+            let config = self.config.protocol;
+            let actions = self
+                .network
+                .with_node(address, |node, network| node.leave(&config, network.rng()));
+
+            if let Some(actions) = actions {
+                self.apply(address, actions);
+            }
+        }
+
+        if self.network.remove(address).is_some() {
+            match departure {
+                Departure::Graceful => self.churn_stats.graceful += 1,
+                Departure::Crash => self.churn_stats.crashes += 1,
+            }
+        }
+    }
+
+    /// Schedules the end of the session of the node in `slot`, if churn is
+    /// driven by a model rather than a trace.
+    fn schedule_departure(&mut self, slot: u64) {
+        let Some(model) = self.churn else {
+            return;
+        };
+
+        let rng = self.network.rng();
+        let session = model.session.sample(rng);
+        let departure = if rng.gen_bool(model.graceful.clamp(0.0, 1.0)) {
+            Departure::Graceful
+        } else {
+            Departure::Crash
+        };
+
+        self.schedule_in(session, Event::Down { slot, departure });
+    }
+
+    /// Drops expired values from every node, if any may have expired.
+    fn expire(&mut self) {
+        if self.now < self.next_expiry {
+            return;
+        }
+
+        let now = self.now;
+        let mut next_expiry = Time::MAX;
+
+        for node in self.network.nodes_mut() {
+            self.expired += node.expire(now) as u64;
+            next_expiry = next_expiry.min(node.next_expiry().unwrap_or(Time::MAX));
+        }
+
+        self.next_expiry = next_expiry;
+    }
+
+    /// Writes the event built by `event` to the trace, if there is one.
+    fn record(&mut self, node: GUID, event: impl FnOnce() -> TraceEvent) {
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.record(self.now, &node, &event());
+        }
+    }
+
+    fn measure(&mut self) {
+        self.divergence.push((self.now, self.divergence()));
+    }
+
+    /// Carries out the actions returned by the node at `address`.
+    fn apply(&mut self, address: Address, actions: Vec<Action>) {
+        let Some(node) = self.network.get(address) else {
+            return;
+        };
+        let guid = node.guid();
+
+        if let Some(expiry) = node.next_expiry() {
+            self.next_expiry = self.next_expiry.min(expiry);
+        }
+
+        for action in actions {
+            if let Action::Complete(result) = &action {
+                self.record(guid, || result.into());
+            }
+
+            match action {
+                Action::Send { to, rpc } => {
+                    let Ok(frame) = rpc.to_frame() else {
+                        let reason = DropReason::Oversized;
+                        let bytes = HEADER_LEN + rpc.to_bytes().len();
+                        self.record(guid, || TraceEvent::drop(&to, reason, &rpc, bytes));
+                        continue;
+                    };
+                    let bytes = frame.len();
+                    self.metrics.record_sent(address, bytes);
+                    self.record(guid, || TraceEvent::send(&to, &rpc, bytes));
+
+                    let latency = self
+                        .latency
+                        .latency(address, to.address, self.network.rng());
+                    let deliveries =
+                        self.links
+                            .deliveries(address, to.address, latency, self.network.rng());
+
+                    if deliveries.is_empty() {
+                        let reason = DropReason::Lost;
+                        self.record(guid, || TraceEvent::drop(&to, reason, &rpc, bytes));
+                    }
+
+                    for delay in deliveries {
+                        let frame = frame.clone();
+                        self.schedule_in(delay, Event::Deliver { to, frame });
+                    }
+                }
+                Action::SetTimer { delay, timer } => {
+                    self.schedule_in(delay, Event::Timer { address, timer });
+                }
+                Action::Trace(event) => self.record(guid, || (&event).into()),
+                Action::Complete(result) if result.operation.is_maintenance() => {
+                    self.metrics.record_operation(&result);
+                    if let Some(stats) = self.maintenance.as_mut() {
+                        match result.operation {
+                            Operation::Refresh { .. } => stats.refreshes += 1,
+                            _ => stats.republishes += 1,
+                        }
+                        stats.messages += result.messages as u64;
+                    }
+                }
+                Action::Complete(result) => {
+                    self.metrics.record_operation(&result);
+                    if let Operation::Store { key, .. } = &result.operation {
+                        self.stored.insert(*key);
+                    }
+                    self.completed.push(result);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        network::Network,
+        node::{Contact, Operation, ProtocolConfig, MAX_FRAME_LEN},
+        primitives::GUID,
+    };
+
+    use super::{
+        trace::test::SharedBuffer, ChurnModel, ChurnTrace, ConstantLatency, Departure, Event,
+        LinkFaults, LinkModel, Partition, SessionDistribution, Simulator, SimulatorConfig, Time,
+        Tracer, UniformLatency,
+    };
+
+    fn fully_connected(n: usize) -> (Simulator, Vec<Contact>) {
+        fully_connected_seeded(n, 0)
+    }
+
+    fn fully_connected_seeded(n: usize, seed: u64) -> (Simulator, Vec<Contact>) {
+        let mut network = Network::new(seed);
+        let contacts = (0..n)
+            .map(|i| network.spawn(&format!("node-{i}")))
+            .collect::<Vec<_>>();
+
+        for a in contacts.iter() {
+            for b in contacts.iter() {
+                network.connect(a.address, *b);
+            }
+        }
+
+        (
+            Simulator::new(network, SimulatorConfig::default()),
+            contacts,
+        )
+    }
+
+    #[test]
+    fn run_until() {
+        let (mut sim, contacts) = fully_connected(2);
+
+        sim.schedule(
+            Time::from_secs(5),
+            Event::Leave {
+                address: contacts[0].address,
+                departure: Departure::Crash,
+            },
+        );
+        sim.run_until(Time::from_secs(4));
+
+        assert_eq!(sim.now(), Time::from_secs(4));
+        assert_eq!(sim.network().len(), 2);
+
+        sim.run_until(Time::from_secs(5));
+        assert_eq!(sim.network().len(), 1);
+        assert_eq!(sim.pending(), 0);
+    }
+
+    #[test]
+    fn reproducible() {
+        let run = |seed| {
+            let (mut sim, contacts) = fully_connected_seeded(50, seed);
+            for contact in contacts.iter() {
+                let key = contacts[0].guid;
+                sim.start(contact.address, Operation::Query { key });
+            }
+            sim.run();
+
+            sim.completed()
+                .iter()
+                .map(|result| (result.finished, result.lookup.contacted.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+
+    #[test]
+    fn find_node() {
+        let (mut sim, contacts) = fully_connected(200);
+        let target = *contacts.last().unwrap();
+
+        sim.start(
+            contacts[0].address,
+            Operation::FindNode {
+                target: target.guid,
+            },
+        );
+        sim.run();
+
+        let result = &sim.completed()[0];
+        assert_eq!(result.lookup.closest.first(), Some(&target));
+        assert!(result.lookup.hops >= 1);
+        assert!(result.lookup.failed.is_empty());
+
+        // Every hop is a request and a response
+        let ConstantLatency(latency) = ConstantLatency::default();
+        let elapsed = result.finished - result.started;
+        assert!(elapsed.as_micros() >= 2 * latency.as_micros());
+        assert_eq!(elapsed.as_micros() % (2 * latency.as_micros()), 0);
+    }
+
+    #[test]
+    fn metrics() {
+        let (mut sim, contacts) = fully_connected(100);
+
+        for contact in contacts.iter().take(10) {
+            let target = contacts[99 - contact.address.index()].guid;
+            sim.start(contact.address, Operation::FindNode { target });
+        }
+        sim.run();
+
+        let metrics = sim.metrics();
+        assert_eq!(metrics.operations().len(), 10);
+
+        // Links are reliable and every node is alive
+        let traffic = metrics.traffic().values();
+        let sent = traffic.clone().map(|traffic| traffic.sent).sum::<u64>();
+        let received = traffic.clone().map(|traffic| traffic.received).sum::<u64>();
+        let bytes_sent = traffic.clone().map(|t| t.bytes_sent).sum::<u64>();
+        let bytes_received = traffic.map(|t| t.bytes_received).sum::<u64>();
+        assert_eq!(sent, received);
+        assert_eq!(bytes_sent, bytes_received);
+
+        let report = metrics.report();
+        let find_node = &report.operations["find_node"];
+        assert_eq!(find_node.count, 10);
+        assert_eq!(find_node.failed.max, 0);
+        assert_eq!(find_node.hops_histogram.values().sum::<usize>(), 10);
+        // Every request is answered, and nodes also ping the heads of their
+        // full buckets
+        assert!((find_node.rpcs.mean * 10.0).round() as u64 * 2 <= sent);
+        assert!(report.bandwidth.unwrap().max > 0);
+    }
+
+    #[test]
+    fn malformed_frames_rejected() {
+        let (mut sim, contacts) = fully_connected(2);
+        let to = contacts[0];
+
+        sim.schedule(
+            Time::ZERO,
+            Event::Deliver {
+                to,
+                frame: vec![0, 0, 0, 1, 9],
+            },
+        );
+        sim.schedule(
+            Time::ZERO,
+            Event::Deliver {
+                to,
+                frame: vec![0, 0],
+            },
+        );
+        sim.run();
+
+        assert_eq!(sim.rejected(), 2);
+        assert!(sim.metrics().traffic().is_empty());
+    }
+
+    #[test]
+    fn oversized_value_dropped() {
+        let (mut sim, contacts) = fully_connected(3);
+        let key = contacts[2].guid;
+        let data = vec![0; MAX_FRAME_LEN];
+
+        sim.start(contacts[0].address, Operation::Store { key, data });
+        sim.run();
+
+        // The lookup went through, but no STORE could be sent
+        assert_eq!(sim.completed().len(), 1);
+        for traffic in sim.metrics().traffic().values() {
+            assert!(traffic.bytes_sent < MAX_FRAME_LEN as u64);
+        }
+        for contact in contacts.iter() {
+            let node = sim.network().get(contact.address).unwrap();
+            assert!(node.storage().is_empty());
+        }
+    }
+
+    #[test]
+    fn trace() {
+        let (mut sim, contacts) = fully_connected(20);
+        let target = contacts[19].guid;
+        let buffer = SharedBuffer::default();
+
+        sim.trace(Tracer::new(buffer.clone()));
+        sim.start(contacts[0].address, Operation::FindNode { target });
+        sim.run();
+        sim.finish_trace().unwrap();
+
+        let trace = buffer.contents();
+        let events = trace
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        let count = |event: &str| events.iter().filter(|line| line["event"] == event).count();
+
+        assert!(count("send") > 0);
+        assert_eq!(count("send"), count("receive"));
+        assert!(count("lookup_query") > 0);
+        assert_eq!(count("lookup_query"), count("lookup_response"));
+        assert_eq!(count("operation_done"), 1);
+        assert!(!sim.config.protocol.trace);
+    }
+
+    #[test]
+    fn find_node_with_latency_model() {
+        let run = || {
+            let (sim, contacts) = fully_connected(50);
+            let mut sim = sim.with_latency(UniformLatency {
+                min: Time::from_millis(10),
+                max: Time::from_millis(200),
+            });
+            let target = contacts.last().unwrap().guid;
+
+            sim.start(contacts[0].address, Operation::FindNode { target });
+            sim.run();
+
+            let result = &sim.completed()[0];
+            assert_eq!(result.lookup.closest.first().map(|c| c.guid), Some(target));
+            result.finished - result.started
+        };
+
+        let elapsed = run();
+        assert!(elapsed >= Time::from_millis(20));
+        assert_eq!(run(), elapsed);
+    }
+
+    #[test]
+    fn find_node_lossy_links() {
+        let (sim, contacts) = fully_connected(100);
+        let faults = LinkFaults {
+            drop: 0.3,
+            duplicate: 0.1,
+            ..Default::default()
+        };
+        let mut sim = sim.with_links(LinkModel::new(faults));
+
+        for contact in contacts.iter().take(20) {
+            let target = contacts[99].guid;
+            sim.start(contact.address, Operation::FindNode { target });
+        }
+        sim.run();
+
+        let stats = *sim.links().stats();
+        assert!(stats.dropped > 0 && stats.duplicated > 0);
+        assert_eq!(sim.completed().len(), 20);
+        assert!(sim
+            .completed()
+            .iter()
+            .any(|result| !result.lookup.failed.is_empty()));
+
+        // Every request was either answered or timed out
+        for node in sim.network().nodes() {
+            assert_eq!(node.pending_requests(), 0);
+        }
+    }
+
+    #[test]
+    fn partition_and_heal() {
+        let (mut sim, contacts) = fully_connected(40);
+        let (group_a, group_b) = contacts.split_at(20);
+        let key = GUID::from(42u32);
+        let addresses = |group: &[Contact]| group.iter().map(|c| c.address).collect::<Vec<_>>();
+
+        let partition = Partition::new([addresses(group_a), addresses(group_b)]);
+        sim.partition_between(Time::ZERO, Time::from_secs(60), partition);
+
+        sim.run_until(Time::from_secs(1));
+        for (i, contact) in contacts.iter().enumerate() {
+            let target = contacts[(i * 7) % contacts.len()].guid;
+            sim.start(contact.address, Operation::FindNode { target });
+        }
+        let data = vec![1, 2, 3];
+        sim.start(group_a[0].address, Operation::Store { key, data });
+
+        sim.run_until(Time::from_secs(61));
+        for contact in group_b.iter() {
+            sim.start(contact.address, Operation::Query { key });
+        }
+        sim.run();
+        sim.schedule_in(Time::ZERO, Event::Measure);
+        sim.run();
+
+        let history = sim.divergence_history();
+        let (split, healed, after) = (history[0].1, history[1].1, history[2].1);
+        assert_eq!(history[1].0, Time::from_secs(60));
+
+        // Cross-partition contacts time out and are evicted
+        assert!(healed.cross_links < split.cross_links);
+        assert_eq!((split.keys, healed.keys_missing), (0, 1));
+
+        // Once healed, the value is found again and cached in the other group
+        assert_eq!(after.keys_missing, 0);
+        let found = sim.completed().iter().filter(|r| r.lookup.value.is_some());
+        assert!(found.count() > 0);
+    }
+
+    #[test]
+    fn churn_model() {
+        let run = |graceful| {
+            let (mut sim, _) = fully_connected(30);
+            let session = SessionDistribution::exponential(Time::from_secs(300)).unwrap();
+            let downtime = SessionDistribution::exponential(Time::from_secs(60)).unwrap();
+            sim.churn(ChurnModel {
+                session,
+                downtime,
+                graceful,
+            });
+            sim.run_until(Time::from_secs(600));
+
+            assert!(sim.churn_stats().joins > 0);
+            assert!(sim.network().len() > 10);
+            assert!(sim.divergence().stale_links > 0);
+
+            *sim.churn_stats()
+        };
+
+        let stats = run(0.0);
+        assert_eq!(stats.graceful, 0);
+        assert!(stats.crashes > 30);
+
+        let stats = run(1.0);
+        assert_eq!(stats.crashes, 0);
+        assert!(stats.graceful > 30);
+    }
+
+    #[test]
+    fn graceful_departure_hands_off_values() {
+        let run = |departure| {
+            let (mut sim, contacts) = fully_connected(2);
+            let (leaving, staying) = (contacts[0], contacts[1]);
+            let key = GUID::from(42u32);
+
+            let config = sim.config().protocol;
+            let node = sim.network_mut().get_mut(leaving.address).unwrap();
+            node.store(Time::ZERO, key, vec![1], &config);
+
+            let address = leaving.address;
+            sim.schedule_in(Time::ZERO, Event::Leave { address, departure });
+            sim.run();
+
+            let node = sim.network().resolve(&staying).unwrap();
+            node.storage().contains_key(&key)
+        };
+
+        assert!(run(Departure::Graceful));
+        assert!(!run(Departure::Crash));
+    }
+
+    #[test]
+    fn churn_trace() {
+        let (mut sim, _) = fully_connected(2);
+        let trace = "0,1,up\n1,2,up\n2,1,down,graceful\n3,2,down\n4,1,up\n";
+        sim.replay(&ChurnTrace::from_csv(trace.as_bytes()).unwrap());
+
+        let mut sizes = Vec::new();
+        for secs in 0..5 {
+            sim.run_until(Time::from_secs(secs));
+            sizes.push(sim.network().len());
+        }
+
+        assert_eq!(sizes, [3, 4, 3, 2, 3]);
+        assert_eq!(sim.churn_stats().joins, 3);
+        assert_eq!(sim.churn_stats().graceful, 1);
+        assert_eq!(sim.churn_stats().crashes, 1);
+    }
+
+    #[test]
+    fn join_through_bootstrap() {
+        let (mut sim, contacts) = fully_connected(50);
+        let joining = sim.join("joining", contacts[0]);
+        let key = joining.guid;
+
+        let mut holders = contacts.clone();
+        holders.sort_by_key(|contact| contact.guid.distance(&key));
+        let config = sim.config().protocol;
+        for holder in holders.iter().take(config.lookup.k) {
+            let node = sim.network_mut().get_mut(holder.address).unwrap();
+            node.store(Time::ZERO, key, vec![1], &config);
+        }
+        sim.run();
+
+        let join = sim.joins().next().unwrap();
+        assert_eq!(join.address, joining.address);
+        assert_eq!(join.lookup.closest.first(), holders.first());
+        assert!(join.finished - join.started >= Time::from_millis(200));
+        assert!(join.messages > join.lookup.contacted.len());
+
+        let node = sim.network().resolve(&joining).unwrap();
+        assert!(node.peers().len() > sim.config().protocol.lookup.k);
+        assert_eq!(node.storage()[&key].data, [1]);
+
+        // Existing nodes learnt of the new node along the way
+        let known_by = sim
+            .network()
+            .nodes()
+            .filter(|node| node.peers().contains(&joining.guid))
+            .count();
+        assert!(known_by >= sim.config().protocol.lookup.k);
+    }
+
+    #[test]
+    fn maintenance() {
+        let (mut sim, contacts) = fully_connected(30);
+        let key = GUID::from(42u32);
+        let hour = sim.config().protocol.republish_interval;
+
+        sim.start(contacts[0].address, Operation::Store { key, data: vec![1] });
+        sim.maintain();
+        sim.run_until(hour - Time::from_secs(1));
+        assert_eq!(
+            sim.maintenance_stats()
+                .copied()
+                .unwrap_or_default()
+                .messages,
+            0
+        );
+
+        sim.run_until(hour + Time::from_secs(60));
+        let stats = *sim.maintenance_stats().unwrap();
+        let k = sim.config().protocol.lookup.k as u64;
+
+        // Every replica republishes, but the original publisher waits a day
+        assert_eq!(stats.republishes, k);
+        assert!(stats.refreshes > 0);
+        assert!(stats.messages > stats.republishes * k);
+        assert!(sim
+            .completed()
+            .iter()
+            .all(|r| !r.operation.is_maintenance()));
+
+        // Timers set themselves again
+        sim.run_until(hour + hour + Time::from_secs(60));
+        let later = *sim.maintenance_stats().unwrap();
+        assert!(later.refreshes > stats.refreshes);
+        assert!(later.republishes > stats.republishes);
+    }
+
+    #[test]
+    fn values_expire() {
+        let mut network = Network::new(0);
+        let publisher = network.spawn("publisher");
+        let holder = network.spawn_with_peers("holder", &[publisher]);
+        network.connect(publisher.address, holder);
+
+        let config = SimulatorConfig {
+            protocol: ProtocolConfig {
+                value_ttl: Time::from_secs(60),
+                ..Default::default()
+            },
+        };
+        let key = holder.guid;
+        let mut sim = Simulator::new(network, config);
+
+        sim.start(publisher.address, Operation::Store { key, data: vec![1] });
+        sim.run_until(Time::from_secs(60));
+
+        let node = sim.network().resolve(&holder).unwrap();
+        assert!(node.storage().contains_key(&key));
+        assert_eq!(sim.expired(), 0);
+
+        sim.schedule(Time::from_secs(61), Event::Measure);
+        sim.run();
+
+        let node = sim.network().resolve(&holder).unwrap();
+        assert!(node.storage().is_empty());
+        assert_eq!(sim.expired(), 1);
+        assert_eq!(sim.lost_keys(), [key]);
+    }
+
+    #[test]
+    fn find_node_removed_contacts_time_out() {
+        let (mut sim, contacts) = fully_connected(50);
+        let target = *contacts.last().unwrap();
+
+        sim.network_mut().remove(target.address);
+        sim.start(
+            contacts[0].address,
+            Operation::FindNode {
+                target: target.guid,
+            },
+        );
+        sim.run();
+
+        let result = &sim.completed()[0];
+        assert!(result.lookup.failed.contains(&target));
+        assert!(!result.lookup.closest.contains(&target));
+        assert!(result.finished - result.started >= sim.config().protocol.rpc_timeout);
+
+        let node = sim.network().get(contacts[0].address).unwrap();
+        assert!(!node.peers().contains(&target.guid));
+    }
+
+    #[test]
+    fn store_and_query() {
+        let (mut sim, contacts) = fully_connected(100);
+        let key = GUID::from(42u32);
+        let data = b"hello".to_vec();
+
+        sim.start(
+            contacts[0].address,
+            Operation::Store {
+                key,
+                data: data.clone(),
+            },
+        );
+        sim.run();
+
+        let stored = sim.drain_completed().next().unwrap();
+        assert!(sim.lost_keys().is_empty());
+        assert_eq!(stored.lookup.closest.len(), sim.config().protocol.lookup.k);
+        for contact in stored.lookup.closest.iter() {
+            let node = sim.network().resolve(contact).unwrap();
+            assert_eq!(node.storage()[&key].data, data);
+        }
+
+        for contact in contacts.iter() {
+            sim.start(contact.address, Operation::Query { key });
+        }
+        sim.start(
+            contacts[0].address,
+            Operation::Query {
+                key: GUID::from(7u32),
+            },
+        );
+        sim.run();
+
+        let (found, missing): (Vec<_>, Vec<_>) = sim
+            .completed()
+            .iter()
+            .partition(|result| result.operation.target() == Some(key));
+
+        assert_eq!(found.len(), contacts.len());
+        assert!(found
+            .iter()
+            .all(|result| result.lookup.value == Some(data.clone())));
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].lookup.value, None);
+    }
+
+    #[test]
+    fn query_caches_at_closest_without_value() {
+        let mut network = Network::new(0);
+        let holder = network.spawn("holder");
+        let relay = network.spawn_with_peers("relay", &[holder]);
+        let requester = network.spawn_with_peers("requester", &[relay]);
+        let key = holder.guid;
+
+        network.get_mut(holder.address).unwrap().store(
+            Time::ZERO,
+            key,
+            vec![1, 2, 3],
+            &ProtocolConfig::default(),
+        );
+
+        let mut sim = Simulator::new(network, SimulatorConfig::default());
+        sim.start(requester.address, Operation::Query { key });
+        sim.run();
+
+        let result = &sim.completed()[0];
+        assert_eq!(result.lookup.value, Some(vec![1, 2, 3]));
+        assert_eq!(result.lookup.hops, 2);
+
+        let relay = sim.network().resolve(&relay).unwrap();
+        assert_eq!(relay.storage()[&key].data, [1, 2, 3]);
+    }
+}
@@ -0,0 +1,180 @@
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{
+    codec::{Decode, DecodeError, Decoder, Encode},
+    network::{Address, Network},
+    node::DATA,
+    primitives::GUID,
+};
+
+/// Split of the network into groups of nodes which cannot reach each other.
+///
+/// Nodes which are not listed in any group form one more, implicit group.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Partition {
+    groups: IndexMap<Address, usize>,
+    count: usize,
+}
+
+impl Partition {
+    pub fn new<G>(groups: impl IntoIterator<Item = G>) -> Self
+    where
+        G: IntoIterator<Item = Address>,
+    {
+        let mut partition = Self::default();
+
+        for group in groups {
+            for address in group {
+                partition.groups.insert(address, partition.count);
+            }
+            partition.count += 1;
+        }
+
+        partition
+    }
+
+    /// Group `address` belongs to.
+    pub fn group(&self, address: Address) -> usize {
+        self.groups.get(&address).copied().unwrap_or(self.count)
+    }
+
+    pub fn can_reach(&self, from: Address, to: Address) -> bool {
+        self.group(from) == self.group(to)
+    }
+
+    /// How far the state of `network` has diverged across groups.
+    pub fn divergence(&self, network: &Network) -> Divergence {
+        let mut divergence = Divergence::default();
+        let groups = network
+            .nodes()
+            .map(|node| self.group(node.address()))
+            .collect::<IndexSet<_>>();
+
+        let mut replicas = IndexMap::<GUID, Vec<(usize, &Vec<DATA>)>>::new();
+
+        for node in network.nodes() {
+            let group = self.group(node.address());
+
+            for contact in node.peers().contacts() {
+                divergence.links += 1;
+
+                if network.resolve(contact).is_none() {
+                    divergence.stale_links += 1;
+                } else if self.group(contact.address) != group {
+                    divergence.cross_links += 1;
+                }
+            }
+
+            for (key, value) in node.storage() {
+                replicas.entry(*key).or_default().push((group, &value.data));
+            }
+        }
+
+        for replicas in replicas.values() {
+            let held_by = replicas
+                .iter()
+                .map(|(group, _)| *group)
+                .collect::<IndexSet<_>>();
+            let values = replicas
+                .iter()
+                .map(|(_, data)| *data)
+                .collect::<IndexSet<_>>();
+
+            divergence.keys += 1;
+            divergence.keys_missing += usize::from(held_by.len() < groups.len());
+            divergence.keys_conflicting += usize::from(values.len() > 1);
+        }
+
+        divergence
+    }
+}
+
+/// Snapshot of how routing tables and stored keys differ across the groups
+/// of a [`Partition`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Divergence {
+    /// Routing table entries, across all nodes.
+    pub links: usize,
+    /// Routing table entries pointing to a live node in another group.
+    pub cross_links: usize,
+    /// Routing table entries pointing to nodes no longer in the network.
+    pub stale_links: usize,
+    /// Keys stored by at least one node.
+    pub keys: usize,
+    /// Keys with no replica in at least one group.
+    pub keys_missing: usize,
+    /// Keys whose replicas do not all hold the same value.
+    pub keys_conflicting: usize,
+}
+
+impl Encode for Partition {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.groups.encode(out);
+        self.count.encode(out);
+    }
+}
+
+impl Decode for Partition {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            groups: IndexMap::decode(input)?,
+            count: usize::decode(input)?,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{network::Network, node::ProtocolConfig, primitives::GUID, simulator::Time};
+
+    use super::Partition;
+
+    #[test]
+    fn groups() {
+        let mut network = Network::new(0);
+        let contacts = (0..5)
+            .map(|i| network.spawn(&format!("node-{i}")))
+            .collect::<Vec<_>>();
+        let address = |i: usize| contacts[i].address;
+
+        let partition = Partition::new([[address(0), address(1)], [address(2), address(3)]]);
+
+        assert!(partition.can_reach(address(0), address(1)));
+        assert!(!partition.can_reach(address(1), address(2)));
+        assert!(!partition.can_reach(address(3), address(4)));
+        assert_eq!(partition.group(address(4)), 2);
+    }
+
+    #[test]
+    fn divergence() {
+        let mut network = Network::new(0);
+        let a = network.spawn("a");
+        let b = network.spawn_with_peers("b", &[a]);
+        let d = network.spawn("d");
+        let c = network.spawn_with_peers("c", &[a, b, d]);
+        let partition = Partition::new([vec![a.address, b.address], vec![c.address]]);
+        let config = ProtocolConfig::default();
+
+        network
+            .get_mut(a.address)
+            .unwrap()
+            .store(Time::ZERO, GUID::MIN, vec![1], &config);
+        network
+            .get_mut(c.address)
+            .unwrap()
+            .store(Time::ZERO, GUID::MIN, vec![2], &config);
+        network
+            .get_mut(b.address)
+            .unwrap()
+            .store(Time::ZERO, GUID::MAX, vec![3], &config);
+        network.remove(d.address);
+
+        let divergence = partition.divergence(&network);
+        assert_eq!(divergence.links, 4);
+        assert_eq!(divergence.stale_links, 1);
+        assert_eq!(divergence.cross_links, 2);
+        assert_eq!(divergence.keys, 2);
+        assert_eq!(divergence.keys_missing, 1);
+        assert_eq!(divergence.keys_conflicting, 1);
+    }
+}
@@ -0,0 +1,284 @@
+use std::path::Path;
+
+use crate::{
+    codec::{Decode, DecodeError, Decoder, Encode},
+    network::Network,
+    node::ProtocolConfig,
+};
+
+use super::{
+    ChurnStats, EventQueue, LinkModel, MaintenanceStats, Partition, Simulator, SimulatorConfig,
+    Time,
+};
+
+/// Start of every snapshot file.
+const MAGIC: &[u8; 8] = b"p2psnap\0";
+/// Bumped whenever the layout of snapshots changes.
+const VERSION: u8 = 3;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    /// The file does not start like a snapshot.
+    FormatInvalid,
+    /// The snapshot was written by an incompatible version of the simulator.
+    VersionUnsupported {
+        version: u8,
+    },
+    Decode(DecodeError),
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "Failed to read or write snapshot: {err}"),
+            SnapshotError::FormatInvalid => write!(f, "Not a simulator snapshot"),
+            SnapshotError::VersionUnsupported { version } => write!(
+                f,
+                "Unsupported snapshot version {version}, expected {VERSION}"
+            ),
+            SnapshotError::Decode(err) => write!(f, "Corrupted snapshot: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(err: std::io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+impl From<DecodeError> for SnapshotError {
+    fn from(err: DecodeError) -> Self {
+        SnapshotError::Decode(err)
+    }
+}
+
+impl Simulator {
+    /// Encodes the state of the simulation: the network with every node's
+    /// routing table, storage and operations in progress, the pending
+    /// events, the RNG, the virtual clock, the link model and the churn model.
+    ///
+    /// The latency model and the tracer are left out, and must be set again
+    /// on the restored simulator. So are the results
+    /// recorded so far (completed operations, metrics and divergence
+    /// history): a restored simulator only records what happens after the
+    /// snapshot.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+
+        self.config.encode(&mut out);
+        self.links.encode(&mut out);
+        self.network.encode(&mut out);
+        self.queue.encode(&mut out);
+        self.now.encode(&mut out);
+        self.partition.encode(&mut out);
+        self.last_partition.encode(&mut out);
+        self.churn.encode(&mut out);
+        self.slots.encode(&mut out);
+        self.churn_stats.encode(&mut out);
+        self.stored.encode(&mut out);
+        self.maintenance.encode(&mut out);
+        self.next_expiry.encode(&mut out);
+        self.expired.encode(&mut out);
+        self.processed.encode(&mut out);
+        self.rejected.encode(&mut out);
+
+        out
+    }
+
+    /// Writes [`Simulator::snapshot`] to `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        Ok(std::fs::write(path, self.snapshot())?)
+    }
+
+    /// Recreates a simulator from [`Simulator::snapshot`]. Running it gives
+    /// the same results as running the original simulator would have, once
+    /// its latency model is set again.
+    pub fn restore(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut input = Decoder::new(bytes);
+
+        if input.read_bytes(MAGIC.len()) != Ok(MAGIC) {
+            return Err(SnapshotError::FormatInvalid);
+        }
+        let version = input.read_u8()?;
+        if version != VERSION {
+            return Err(SnapshotError::VersionUnsupported { version });
+        }
+
+        let mut config = SimulatorConfig::decode(&mut input)?;
+        // Tracing is enabled along with a tracer, which is not restored
+        config.protocol.trace = false;
+        let links = LinkModel::decode(&mut input)?;
+        let network = Network::decode(&mut input)?;
+
+        let mut sim = Simulator::new(network, config).with_links(links);
+        sim.queue = EventQueue::decode(&mut input)?;
+        sim.now = Time::decode(&mut input)?;
+        sim.partition = Option::decode(&mut input)?;
+        sim.last_partition = Partition::decode(&mut input)?;
+        sim.churn = Option::decode(&mut input)?;
+        sim.slots = Decode::decode(&mut input)?;
+        sim.churn_stats = ChurnStats::decode(&mut input)?;
+        sim.stored = Decode::decode(&mut input)?;
+        sim.maintenance = Option::decode(&mut input)?;
+        sim.next_expiry = Time::decode(&mut input)?;
+        sim.expired = u64::decode(&mut input)?;
+        sim.processed = u64::decode(&mut input)?;
+        sim.rejected = u64::decode(&mut input)?;
+        input.finish()?;
+
+        Ok(sim)
+    }
+
+    /// Reads a snapshot written by [`Simulator::save`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        Self::restore(&std::fs::read(path)?)
+    }
+}
+
+impl Encode for SimulatorConfig {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.protocol.encode(out);
+    }
+}
+
+impl Decode for SimulatorConfig {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            protocol: ProtocolConfig::decode(input)?,
+        })
+    }
+}
+
+impl Encode for MaintenanceStats {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.refreshes.encode(out);
+        self.republishes.encode(out);
+        self.messages.encode(out);
+    }
+}
+
+impl Decode for MaintenanceStats {
+    fn decode(input: &mut Decoder<'_>) -> Result<Self, DecodeError> {
+        Ok(Self {
+            refreshes: u64::decode(input)?,
+            republishes: u64::decode(input)?,
+            messages: u64::decode(input)?,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        network::Network,
+        node::Operation,
+        simulator::{
+            ChurnModel, LinkFaults, LinkModel, SessionDistribution, Simulator, SimulatorConfig,
+            Time, UniformLatency,
+        },
+    };
+
+    use super::{SnapshotError, VERSION};
+
+    fn latency() -> UniformLatency {
+        UniformLatency {
+            min: Time::from_millis(10),
+            max: Time::from_millis(100),
+        }
+    }
+
+    fn warmed_up() -> Simulator {
+        let mut network = Network::new(3);
+        let contacts = (0..40)
+            .map(|i| network.spawn(&format!("node-{i}")))
+            .collect::<Vec<_>>();
+        for a in contacts.iter() {
+            for b in contacts.iter().step_by(3) {
+                network.connect(a.address, *b);
+            }
+        }
+
+        let mut sim = Simulator::new(network, SimulatorConfig::default())
+            .with_latency(latency())
+            .with_links(LinkModel::new(LinkFaults::lossy(0.05)));
+        sim.maintain();
+        sim.churn(ChurnModel {
+            session: SessionDistribution::weibull(Time::from_secs(20 * 60), 0.6).unwrap(),
+            downtime: SessionDistribution::exponential(Time::from_secs(5 * 60)).unwrap(),
+            graceful: 0.5,
+        });
+
+        for (i, contact) in contacts.iter().enumerate().take(10) {
+            let key = contacts[39 - i].guid;
+            sim.start(
+                contact.address,
+                Operation::Store {
+                    key,
+                    data: vec![i as u8],
+                },
+            );
+        }
+        // Stops in the middle of the stores, with requests in flight
+        sim.run_until(Time::from_millis(150));
+
+        sim
+    }
+
+    #[test]
+    fn restored_run_matches() {
+        let mut original = warmed_up();
+        let before = original.completed().len();
+        let snapshot = original.snapshot();
+        let mut restored = Simulator::restore(&snapshot)
+            .unwrap()
+            .with_latency(latency());
+
+        assert_eq!(restored.now(), original.now());
+        assert_eq!(restored.pending(), original.pending());
+        assert_eq!(restored.snapshot(), snapshot);
+
+        let end = Time::from_secs(70 * 60);
+        original.run_until(end);
+        restored.run_until(end);
+
+        let outcome = |sim: &Simulator| {
+            sim.completed()
+                .iter()
+                .map(|result| (result.finished, result.messages, result.lookup.hops))
+                .collect::<Vec<_>>()
+        };
+        assert!(!outcome(&restored).is_empty());
+        assert_eq!(outcome(&restored), outcome(&original)[before..]);
+        assert_eq!(restored.snapshot(), original.snapshot());
+        assert_eq!(restored.maintenance_stats(), original.maintenance_stats());
+        assert!(restored.churn_stats().joins > 0);
+        assert_eq!(restored.churn_stats(), original.churn_stats());
+    }
+
+    #[test]
+    fn invalid() {
+        let snapshot = warmed_up().snapshot();
+
+        assert!(matches!(
+            Simulator::restore(b"not a snapshot"),
+            Err(SnapshotError::FormatInvalid)
+        ));
+
+        let mut newer = snapshot.clone();
+        newer[8] = VERSION + 1;
+        assert!(matches!(
+            Simulator::restore(&newer),
+            Err(SnapshotError::VersionUnsupported { version }) if version == VERSION + 1
+        ));
+
+        assert!(matches!(
+            Simulator::restore(&snapshot[..snapshot.len() / 2]),
+            Err(SnapshotError::Decode(_))
+        ));
+    }
+}
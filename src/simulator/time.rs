@@ -0,0 +1,102 @@
+/// Point in, or span of, virtual time, with microsecond resolution.
+///
+/// Virtual time is fully decoupled from wall time: it only moves forward when
+/// the [`Simulator`](super::Simulator) processes an event.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Time(u64);
+
+impl Time {
+    pub const ZERO: Time = Time(0);
+    pub const MAX: Time = Time(u64::MAX);
+
+    pub const fn from_micros(micros: u64) -> Self {
+        Self(micros)
+    }
+
+    pub const fn from_millis(millis: u64) -> Self {
+        Self(millis * 1_000)
+    }
+
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(secs * 1_000_000)
+    }
+
+    /// Converts fractional seconds, saturating on overflow and clamping
+    /// negative values to zero.
+    pub fn from_secs_f64(secs: f64) -> Self {
+        Self((secs * 1_000_000.0) as u64)
+    }
+
+    pub const fn as_micros(&self) -> u64 {
+        self.0
+    }
+
+    pub const fn as_millis(&self) -> u64 {
+        self.0 / 1_000
+    }
+
+    pub fn as_secs_f64(&self) -> f64 {
+        self.0 as f64 / 1_000_000.0
+    }
+}
+
+impl std::ops::Add for Time {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl std::ops::AddAssign for Time {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl std::ops::Sub for Time {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl std::ops::SubAssign for Time {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl std::fmt::Display for Time {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{:06}s", self.0 / 1_000_000, self.0 % 1_000_000)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::Time;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Time::from_secs(2), Time::from_millis(2_000));
+        assert_eq!(Time::from_millis(3), Time::from_micros(3_000));
+        assert_eq!(Time::from_secs_f64(1.5), Time::from_millis(1_500));
+        assert_eq!(Time::from_secs_f64(-1.0), Time::ZERO);
+        assert_eq!(Time::from_millis(1_500).as_secs_f64(), 1.5);
+    }
+
+    #[test]
+    fn saturating() {
+        assert_eq!(Time::ZERO - Time::from_secs(1), Time::ZERO);
+        assert_eq!(Time::MAX + Time::from_secs(1), Time::MAX);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Time::from_micros(1_000_042).to_string(), "1.000042s");
+        assert_eq!(Time::ZERO.to_string(), "0.000000s");
+    }
+}
@@ -0,0 +1,352 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use serde::Serialize;
+
+use crate::{
+    node::{Contact, Message, NodeEvent, OperationResult, Rpc},
+    primitives::GUID,
+};
+
+use super::{Departure, Time};
+
+/// Summary of an [`Rpc`], leaving out the bulk of its payload.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MessageSummary {
+    pub rpc: String,
+    pub kind: &'static str,
+    /// Target or key the message is about, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    /// Number of contacts carried by a `nodes` response.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contacts: Option<usize>,
+    /// Length of the frame, see [`Rpc::to_frame`].
+    pub bytes: usize,
+}
+
+impl MessageSummary {
+    /// Summary of `rpc`, which takes up a frame of `bytes` bytes.
+    pub fn new(rpc: &Rpc, bytes: usize) -> Self {
+        let key = match &rpc.message {
+            Message::Ping | Message::Pong => None,
+            Message::FindNode { target } | Message::Nodes { target, .. } => Some(target),
+            Message::FindValue { key }
+            | Message::Value { key, .. }
+            | Message::Store { key, .. }
+            | Message::StoreAck { key } => Some(key),
+        };
+        let contacts = match &rpc.message {
+            Message::Nodes { contacts, .. } => Some(contacts.len()),
+            _ => None,
+        };
+
+        Self {
+            rpc: rpc.id.to_hex(),
+            kind: rpc.message.kind(),
+            key: key.map(GUID::to_hex),
+            contacts,
+            bytes,
+        }
+    }
+}
+
+/// Why a message never reached its destination.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropReason {
+    /// Lost on the link, see [`LinkFaults::drop`](super::LinkFaults::drop).
+    Lost,
+    /// Sender and destination are on different sides of a partition.
+    Partition,
+    /// The destination is no longer part of the network.
+    Unreachable,
+    /// The RPC does not fit in a frame, see
+    /// [`MAX_VALUE_LEN`](crate::node::MAX_VALUE_LEN).
+    Oversized,
+}
+
+/// Something which happened in the simulation, from the point of view of one
+/// node.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TraceEvent {
+    Send {
+        to: String,
+        message: MessageSummary,
+    },
+    Receive {
+        from: String,
+        message: MessageSummary,
+    },
+    Drop {
+        to: String,
+        reason: DropReason,
+        message: MessageSummary,
+    },
+    /// A frame which could not be decoded was dropped on receipt.
+    Reject {
+        bytes: usize,
+        error: String,
+    },
+    BucketInsert {
+        peer: String,
+        bucket: usize,
+    },
+    BucketEvict {
+        peer: String,
+        bucket: usize,
+    },
+    Store {
+        key: String,
+        bytes: usize,
+        ttl_us: u64,
+    },
+    LookupQuery {
+        target: String,
+        peer: String,
+    },
+    LookupResponse {
+        target: String,
+        peer: String,
+        contacts: usize,
+        found: bool,
+    },
+    LookupFailure {
+        target: String,
+        peer: String,
+    },
+    OperationDone {
+        operation: &'static str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        target: Option<String>,
+        hops: usize,
+        rpcs: usize,
+        failed: usize,
+        latency_us: u64,
+        found: bool,
+    },
+    Join,
+    Leave {
+        graceful: bool,
+    },
+}
+
+impl TraceEvent {
+    pub fn send(to: &Contact, rpc: &Rpc, bytes: usize) -> Self {
+        TraceEvent::Send {
+            to: to.guid.to_hex(),
+            message: MessageSummary::new(rpc, bytes),
+        }
+    }
+
+    pub fn receive(rpc: &Rpc, bytes: usize) -> Self {
+        TraceEvent::Receive {
+            from: rpc.sender.guid.to_hex(),
+            message: MessageSummary::new(rpc, bytes),
+        }
+    }
+
+    pub fn drop(to: &Contact, reason: DropReason, rpc: &Rpc, bytes: usize) -> Self {
+        TraceEvent::Drop {
+            to: to.guid.to_hex(),
+            reason,
+            message: MessageSummary::new(rpc, bytes),
+        }
+    }
+
+    pub fn leave(departure: Departure) -> Self {
+        TraceEvent::Leave {
+            graceful: departure == Departure::Graceful,
+        }
+    }
+}
+
+impl From<&NodeEvent> for TraceEvent {
+    fn from(event: &NodeEvent) -> Self {
+        match *event {
+            NodeEvent::BucketInsert { contact, bucket } => TraceEvent::BucketInsert {
+                peer: contact.guid.to_hex(),
+                bucket,
+            },
+            NodeEvent::BucketEvict { contact, bucket } => TraceEvent::BucketEvict {
+                peer: contact.guid.to_hex(),
+                bucket,
+            },
+            NodeEvent::Store { key, bytes, ttl } => TraceEvent::Store {
+                key: key.to_hex(),
+                bytes,
+                ttl_us: ttl.as_micros(),
+            },
+            NodeEvent::LookupQuery { target, peer } => TraceEvent::LookupQuery {
+                target: target.to_hex(),
+                peer: peer.guid.to_hex(),
+            },
+            NodeEvent::LookupResponse {
+                target,
+                peer,
+                contacts,
+                found,
+            } => TraceEvent::LookupResponse {
+                target: target.to_hex(),
+                peer: peer.guid.to_hex(),
+                contacts,
+                found,
+            },
+            NodeEvent::LookupFailure { target, peer } => TraceEvent::LookupFailure {
+                target: target.to_hex(),
+                peer: peer.guid.to_hex(),
+            },
+        }
+    }
+}
+
+impl From<&OperationResult> for TraceEvent {
+    fn from(result: &OperationResult) -> Self {
+        TraceEvent::OperationDone {
+            operation: result.operation.kind(),
+            target: result.operation.target().as_ref().map(GUID::to_hex),
+            hops: result.lookup.hops,
+            rpcs: result.messages,
+            failed: result.lookup.failed.len(),
+            latency_us: (result.finished - result.started).as_micros(),
+            found: result.lookup.value.is_some(),
+        }
+    }
+}
+
+/// A [`TraceEvent`] as written out: one JSON object per line.
+#[derive(Serialize)]
+struct TraceRecord<'a> {
+    time_us: u64,
+    node: String,
+    #[serde(flatten)]
+    event: &'a TraceEvent,
+}
+
+/// Writes [`TraceEvent`]s as JSON Lines.
+///
+/// Writing happens as the simulation runs, which cannot stop for I/O errors:
+/// the first error is kept, and returned by [`Tracer::finish`].
+pub struct Tracer {
+    out: Box<dyn Write>,
+    error: Option<std::io::Error>,
+}
+
+impl Tracer {
+    pub fn new(out: impl Write + 'static) -> Self {
+        Self {
+            out: Box::new(out),
+            error: None,
+        }
+    }
+
+    pub fn to_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+
+    pub fn record(&mut self, time: Time, node: &GUID, event: &TraceEvent) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let record = TraceRecord {
+            time_us: time.as_micros(),
+            node: node.to_hex(),
+            event,
+        };
+        let result = serde_json::to_writer(&mut self.out, &record)
+            .map_err(std::io::Error::from)
+            .and_then(|_| self.out.write_all(b"\n"));
+
+        if let Err(err) = result {
+            self.error = Some(err);
+        }
+    }
+
+    /// Flushes the trace, returning the first error met while writing it.
+    pub fn finish(mut self) -> std::io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.out.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::{cell::RefCell, io::Write, rc::Rc};
+
+    use crate::{
+        network::Address,
+        node::{Contact, Message, Rpc},
+        primitives::GUID,
+        simulator::Time,
+    };
+
+    use super::{TraceEvent, Tracer};
+
+    /// In-memory trace output, which stays readable once handed to a
+    /// [`Tracer`].
+    #[derive(Clone, Default)]
+    pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl SharedBuffer {
+        pub fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_lines() {
+        let sender = Contact::new(GUID::from(0xabu8), Address::new(1));
+        let rpc = Rpc {
+            id: GUID::from(1u8),
+            sender,
+            message: Message::FindNode {
+                target: GUID::from(0xffu8),
+            },
+        };
+
+        let buffer = SharedBuffer::default();
+        let mut tracer = Tracer::new(buffer.clone());
+        tracer.record(
+            Time::from_millis(5),
+            &GUID::from(2u8),
+            &TraceEvent::receive(&rpc, 66),
+        );
+        tracer.record(Time::from_millis(6), &GUID::from(2u8), &TraceEvent::Join);
+        tracer.finish().unwrap();
+
+        let trace = buffer.contents();
+        let lines = trace.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                concat!(
+                    r#"{"time_us":5000,"node":"0000000000000000000000000000000000000002","#,
+                    r#""event":"receive","from":"00000000000000000000000000000000000000ab","#,
+                    r#""message":{"rpc":"0000000000000000000000000000000000000001","#,
+                    r#""kind":"find_node","key":"00000000000000000000000000000000000000ff","bytes":66}}"#
+                ),
+                concat!(
+                    r#"{"time_us":6000,"node":"0000000000000000000000000000000000000002","#,
+                    r#""event":"join"}"#
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use std::io::Write;
+
+use indexmap::IndexMap;
+
+use crate::{
+    network::{Address, Network},
+    primitives::GUID,
+};
+
+/// A node of the overlay graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Vertex {
+    pub guid: GUID,
+    pub address: Address,
+    /// Whether the node is still part of the network. Nodes which left are
+    /// only kept as long as some routing table points to them.
+    pub alive: bool,
+}
+
+/// A routing table entry of `from`, pointing to `to`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub from: GUID,
+    pub to: GUID,
+    /// Index of the bucket of `from` holding the entry.
+    pub bucket: usize,
+}
+
+/// Snapshot of the overlay formed by the routing tables of every node in a
+/// [`Network`], as a directed graph.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Overlay {
+    vertices: IndexMap<GUID, Vertex>,
+    edges: Vec<Edge>,
+}
+
+impl Overlay {
+    pub fn new(network: &Network) -> Self {
+        let mut overlay = Self::default();
+
+        for node in network.nodes() {
+            overlay.vertices.insert(
+                node.guid(),
+                Vertex {
+                    guid: node.guid(),
+                    address: node.address(),
+                    alive: true,
+                },
+            );
+        }
+
+        for node in network.nodes() {
+            for (bucket, contacts) in node.peers().buckets().enumerate() {
+                for contact in contacts.contacts() {
+                    overlay.vertices.entry(contact.guid).or_insert(Vertex {
+                        guid: contact.guid,
+                        address: contact.address,
+                        alive: false,
+                    });
+                    overlay.edges.push(Edge {
+                        from: node.guid(),
+                        to: contact.guid,
+                        bucket,
+                    });
+                }
+            }
+        }
+
+        overlay
+    }
+
+    pub fn vertices(&self) -> impl Iterator<Item = &Vertex> {
+        self.vertices.values()
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Writes the overlay in the Graphviz DOT format.
+    pub fn write_dot(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "digraph overlay {{")?;
+
+        for vertex in self.vertices() {
+            write!(
+                out,
+                "  \"{}\" [label=\"{}\", address={}",
+                vertex.guid.to_hex(),
+                vertex.guid.to_hex(),
+                vertex.address.index()
+            )?;
+            if !vertex.alive {
+                write!(out, ", style=dashed")?;
+            }
+            writeln!(out, "];")?;
+        }
+
+        for edge in self.edges.iter() {
+            writeln!(
+                out,
+                "  \"{}\" -> \"{}\" [bucket={}, label={}];",
+                edge.from.to_hex(),
+                edge.to.to_hex(),
+                edge.bucket,
+                edge.bucket
+            )?;
+        }
+
+        writeln!(out, "}}")
+    }
+
+    /// Writes the overlay in the GraphML format.
+    pub fn write_graphml(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            out,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+        writeln!(
+            out,
+            r#"  <key id="label" for="node" attr.name="label" attr.type="string"/>"#
+        )?;
+        writeln!(
+            out,
+            r#"  <key id="address" for="node" attr.name="address" attr.type="int"/>"#
+        )?;
+        writeln!(
+            out,
+            r#"  <key id="alive" for="node" attr.name="alive" attr.type="boolean"/>"#
+        )?;
+        writeln!(
+            out,
+            r#"  <key id="bucket" for="edge" attr.name="bucket" attr.type="int"/>"#
+        )?;
+        writeln!(out, r#"  <graph id="overlay" edgedefault="directed">"#)?;
+
+        for vertex in self.vertices() {
+            let guid = vertex.guid.to_hex();
+            writeln!(out, r#"    <node id="{guid}">"#)?;
+            writeln!(out, r#"      <data key="label">{guid}</data>"#)?;
+            writeln!(
+                out,
+                r#"      <data key="address">{}</data>"#,
+                vertex.address.index()
+            )?;
+            writeln!(out, r#"      <data key="alive">{}</data>"#, vertex.alive)?;
+            writeln!(out, "    </node>")?;
+        }
+
+        for edge in self.edges.iter() {
+            writeln!(
+                out,
+                r#"    <edge source="{}" target="{}">"#,
+                edge.from.to_hex(),
+                edge.to.to_hex()
+            )?;
+            writeln!(out, r#"      <data key="bucket">{}</data>"#, edge.bucket)?;
+            writeln!(out, "    </edge>")?;
+        }
+
+        writeln!(out, "  </graph>")?;
+        writeln!(out, "</graphml>")
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::network::Network;
+
+    use super::Overlay;
+
+    #[test]
+    fn edges() {
+        let mut network = Network::new(0);
+        let a = network.spawn("a");
+        let b = network.spawn_with_peers("b", &[a]);
+        let c = network.spawn_with_peers("c", &[a, b]);
+        network.remove(a.address);
+
+        let overlay = Overlay::new(&network);
+        assert_eq!(overlay.vertices().count(), 3);
+        assert_eq!(overlay.edges().len(), 3);
+        assert!(!overlay.vertices().find(|v| v.guid == a.guid).unwrap().alive);
+
+        let node = network.get(c.address).unwrap();
+        for edge in overlay.edges().iter().filter(|edge| edge.from == c.guid) {
+            assert_eq!(node.peers().bucket_index(&edge.to), Some(edge.bucket));
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let mut network = Network::new(0);
+        let a = network.spawn("a");
+        let b = network.spawn_with_peers("b", &[a]);
+        let bucket = network
+            .get(b.address)
+            .unwrap()
+            .peers()
+            .bucket_index(&a.guid);
+
+        let overlay = Overlay::new(&network);
+
+        let mut dot = Vec::new();
+        overlay.write_dot(&mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with("digraph overlay {\n"));
+        assert!(dot.contains(&format!(
+            "\"{}\" -> \"{}\" [bucket={}",
+            b.guid.to_hex(),
+            a.guid.to_hex(),
+            bucket.unwrap()
+        )));
+
+        let mut graphml = Vec::new();
+        overlay.write_graphml(&mut graphml).unwrap();
+        let graphml = String::from_utf8(graphml).unwrap();
+        assert_eq!(graphml.matches("<node ").count(), 2);
+        assert_eq!(graphml.matches("<edge ").count(), 1);
+        assert!(graphml.contains(&format!(
+            r#"<edge source="{}" target="{}">"#,
+            b.guid.to_hex(),
+            a.guid.to_hex()
+        )));
+    }
+}